@@ -26,9 +26,13 @@ impl ApplicationHandler for App {
 
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
 
-        self.state = Some(pollster::block_on(async {
-            State::new(window.into()).await
-        }));
+        match pollster::block_on(State::new(window)) {
+            Ok(state) => self.state = Some(state),
+            Err(e) => {
+                eprintln!("wgpu initialization failed: {e}");
+                event_loop.exit();
+            }
+        }
     }
 
     fn window_event(
@@ -8,28 +8,44 @@ pub struct State {
 }
 
 impl State {
-    pub async fn new(window: Arc<Window>) -> Self {
-        let init = wgpu_simplified::InitWgpu::init_wgpu(window, 1).await;
+    pub async fn new(window: Arc<Window>) -> anyhow::Result<Self> {
+        let init = wgpu_simplified::InitWgpu::try_init_wgpu(window, 1).await?;
+
+        // List every adapter that can present to the window and mark the selected one. The
+        // backends are the ones the adapter was chosen from.
+        let selected = init.adapter.get_info();
+        let adapters = wgpu_simplified::enumerate_adapters(
+            &init.instance,
+            init.options.backends,
+            init.surface.as_ref(),
+        )
+        .await;
+        println!("Adapters (set WGPU_ADAPTER to choose another):");
+        for adapter in adapters {
+            let info = adapter.get_info();
+            let mark = if info == selected { "*" } else { " " };
+            println!(
+                "{} {} ({:?}, {:?})",
+                mark, info.name, info.device_type, info.backend
+            );
+        }
+
+        println!("Surface format: {:?}", init.config.format);
 
         println!("{:#?}", init.adapter.get_info());
         println!("Adapter{:#?}", init.adapter.limits());
         println!("Device{:#?}", init.device.limits());
 
-        Self { init }
+        Ok(Self { init })
     }
 
     pub fn window(&self) -> &Window {
-        &self.init.window
+        self.init.window()
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
-            // The surface needs to be reconfigured every time the window is resized.
-            self.init.config.width = width;
-            self.init.config.height = height;
-            self.init
-                .surface
-                .configure(&self.init.device, &self.init.config);
+            self.init.resize(width, height);
         }
     }
 
@@ -13,7 +13,10 @@ fn main() {
     let title = "ch01 triangle vertex color";
 
     let inputs = state::Inputs {
-        source: wgpu::ShaderSource::Wgsl(include_str!("triangle_vertex_color.wgsl").into()),
+        shader: state::compose_shader(
+            "triangle_vertex_color.wgsl",
+            include_str!("triangle_vertex_color.wgsl"),
+        ),
         topology: wgpu::PrimitiveTopology::TriangleList,
         strip_index_format: None,
     };
@@ -21,7 +24,7 @@ fn main() {
     let _ = run(&title, inputs, 3);
 }
 
-pub fn run(title: &'static str, inputs: Inputs<'static>, num_vertices: u32) -> anyhow::Result<()> {
+pub fn run(title: &'static str, inputs: Inputs, num_vertices: u32) -> anyhow::Result<()> {
     env_logger::init();
 
     let event_loop = EventLoop::builder().build()?;
@@ -1,7 +1,4 @@
-struct Output {
-    @builtin(position) Position: vec4f,
-    @location(0) vColor: vec4f
-}
+#include "vertex_color.wgsl"
 
 @vertex
 fn vs_main(@builtin(vertex_index) VertexIndex: u32) -> Output {
@@ -22,8 +19,3 @@ fn vs_main(@builtin(vertex_index) VertexIndex: u32) -> Output {
 
     return output;
 }
-
-@fragment
-fn fs_main(@location(0) vColor: vec4f) -> @location(0) vec4f {
-    return vColor;
-}
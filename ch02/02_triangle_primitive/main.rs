@@ -30,14 +30,17 @@ fn main() {
     let title = "ch01 Primitive ".to_owned() + primitive_type;
 
     let inputs = Inputs {
-        source: wgpu::ShaderSource::Wgsl(include_str!("triangle_primitive.wgsl").into()),
+        shader: state::compose_shader(
+            "triangle_primitive.wgsl",
+            include_str!("triangle_primitive.wgsl"),
+        ),
         topology: topology,
         strip_index_format: index_format,
     };
 
     let _ = run(&title, inputs, 9);
 
-    pub fn run(title: &str, inputs: Inputs<'static>, num_vertices: u32) -> anyhow::Result<()> {
+    pub fn run(title: &str, inputs: Inputs, num_vertices: u32) -> anyhow::Result<()> {
         env_logger::init();
 
         let event_loop = EventLoop::builder().build()?;
@@ -8,10 +8,13 @@ use winit::{
 };
 
 use crate::state::State;
+use wgpu_fundamentals::wgpu_simplified as ws;
 
 #[derive(Default)]
 pub struct App {
     state: Option<State>,
+    window: Option<Arc<Window>>,
+    recovery: ws::DeviceRecovery,
     pub title: &'static str,
 }
 
@@ -20,6 +23,7 @@ impl ApplicationHandler for App {
         let window_attributes = Window::default_attributes().with_title(self.title);
 
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        self.window = Some(window.clone());
 
         self.state = Some(pollster::block_on(async {
             State::new(window.into()).await
@@ -45,7 +49,17 @@ impl ApplicationHandler for App {
                 state.resize(size.width, size.height);
             }
             WindowEvent::RedrawRequested => {
-                let _ = state.render();
+                if let Err(e) = state.render() {
+                    log::error!("render failed: {e}");
+                }
+                if state.is_device_lost() {
+                    // Nothing created on the old device can be reused; only its InitWgpu is
+                    // kept, so about_to_wait requests a new device with the same settings.
+                    if let Some(state) = self.state.take() {
+                        self.recovery.start(state.init);
+                    }
+                    return;
+                }
                 // Emits a new redraw requested event.
                 state.window().request_redraw();
 
@@ -64,7 +78,10 @@ impl ApplicationHandler for App {
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(init) = self.recovery.poll(event_loop) {
+            self.state = Some(State::with_init(init));
+        }
         if let Some(state) = &self.state {
             state.window().request_redraw();
         }
@@ -4,21 +4,37 @@ use wgpu::util::DeviceExt;
 use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
 
 use crate::vertex::{VERTICES, Vertex};
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::shader_composer::ShaderComposer;
 use wgpu_fundamentals::wgpu_simplified as ws;
 
 pub struct State {
-    init: ws::InitWgpu,
+    pub init: ws::InitWgpu,
     pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
+    capture_requested: bool,
 }
 
 impl State {
     pub async fn new(window: Arc<Window>) -> Self {
-        let init = ws::InitWgpu::init_wgpu(window, 1).await;
+        Self::with_init(ws::InitWgpu::init_wgpu(window, 1).await)
+    }
 
-        let shader = init
-            .device
-            .create_shader_module(wgpu::include_wgsl!("triangle_gpu_buffer.wgsl"));
+    pub fn with_init(init: ws::InitWgpu) -> Self {
+        let shader = ShaderComposer::new()
+            .add_file(
+                "vertex_color.wgsl",
+                include_str!("../../shaders/vertex_color.wgsl"),
+            )
+            .add_file(
+                "triangle_gpu_buffer.wgsl",
+                include_str!("triangle_gpu_buffer.wgsl"),
+            )
+            .compose("triangle_gpu_buffer.wgsl")
+            .and_then(|composed| {
+                composed.create_shader_module(&init.device, "triangle_gpu_buffer.wgsl")
+            })
+            .unwrap_or_else(|e| panic!("{e}"));
 
         let pipeline_layout = init
             .device
@@ -55,21 +71,21 @@ impl State {
             init,
             pipeline,
             vertex_buffer,
+            capture_requested: false,
         }
     }
 
     pub fn window(&self) -> &Window {
-        &self.init.window
+        self.init.window()
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.init.is_device_lost()
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
-            // The surface needs to be reconfigured every time the window is resized.
-            self.init.config.width = width;
-            self.init.config.height = height;
-            self.init
-                .surface
-                .configure(&self.init.device, &self.init.config);
+            self.init.resize(width, height);
         }
     }
 
@@ -78,6 +94,9 @@ impl State {
             (KeyCode::Escape, true) => {
                 event_loop.exit();
             }
+            (KeyCode::F12, true) => {
+                self.capture_requested = true;
+            }
             _ => {}
         }
     }
@@ -87,34 +106,15 @@ impl State {
     }
 
     pub fn render(&mut self) -> anyhow::Result<()> {
-        let output = match self.init.surface.get_current_texture() {
-            wgpu::CurrentSurfaceTexture::Success(surface_texture) => surface_texture,
-            wgpu::CurrentSurfaceTexture::Suboptimal(surface_texture) => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                surface_texture
-            }
-            wgpu::CurrentSurfaceTexture::Timeout
-            | wgpu::CurrentSurfaceTexture::Occluded
-            | wgpu::CurrentSurfaceTexture::Validation => {
-                // Skip this frame
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Outdated => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Lost => {
-                anyhow::bail!("Lost device");
-            }
-        };
+        if self.init.is_device_lost() {
+            anyhow::bail!("Lost device");
+        }
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let Some(output) = self.init.acquire_frame()? else {
+            // Skip this frame
+            return Ok(());
+        };
+        let view = output.create_view();
 
         let mut encoder =
             self.init
@@ -144,6 +144,14 @@ impl State {
         // Tell the wgpu to finish the command buffer and send it to the
         // GPU's render queue
         self.init.queue.submit(std::iter::once(encoder.finish()));
+
+        // read the frame back before it is presented
+        if std::mem::take(&mut self.capture_requested) {
+            match capture::save_screenshot(&self.init, output.texture()) {
+                Ok(path) => println!("Saved {}", path.display()),
+                Err(e) => log::error!("{e}"),
+            }
+        }
         output.present();
 
         Ok(())
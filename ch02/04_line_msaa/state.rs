@@ -3,6 +3,8 @@ use std::sync::Arc;
 use wgpu::util::DeviceExt;
 use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
 
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::shader_composer::ShaderComposer;
 use wgpu_fundamentals::wgpu_simplified as ws;
 
 #[repr(C)]
@@ -13,20 +15,34 @@ pub struct Vertex {
 }
 
 pub struct State {
-    init: ws::InitWgpu,
+    pub init: ws::InitWgpu,
     pipeline: wgpu::RenderPipeline,
     vertex_buffer: wgpu::Buffer,
     vertex_count: u32,
-    msaa_texture_view: wgpu::TextureView,
+    render_target: ws::RenderTarget,
+    capture_requested: bool,
 }
 
 impl State {
     pub async fn new(window: Arc<Window>, sample_count: u32) -> Self {
-        let init = ws::InitWgpu::init_wgpu(window, sample_count).await;
+        Self::with_init(ws::InitWgpu::init_wgpu(window, sample_count).await)
+    }
 
-        let shader = init
-            .device
-            .create_shader_module(wgpu::include_wgsl!("triangle_gpu_buffer.wgsl"));
+    pub fn with_init(init: ws::InitWgpu) -> Self {
+        let shader = ShaderComposer::new()
+            .add_file(
+                "vertex_color.wgsl",
+                include_str!("../../shaders/vertex_color.wgsl"),
+            )
+            .add_file(
+                "triangle_gpu_buffer.wgsl",
+                include_str!("triangle_gpu_buffer.wgsl"),
+            )
+            .compose("triangle_gpu_buffer.wgsl")
+            .and_then(|composed| {
+                composed.create_shader_module(&init.device, "triangle_gpu_buffer.wgsl")
+            })
+            .unwrap_or_else(|e| panic!("{e}"));
 
         let pipeline_layout = init
             .device
@@ -52,7 +68,7 @@ impl State {
         };
         let pipeline = ppl.new(&init);
 
-        let msaa_texture_view = ws::create_msaa_texture_view(&init);
+        let render_target = ws::RenderTarget::without_depth(&init);
 
         let mut vertex_data = vec![];
 
@@ -85,25 +101,23 @@ impl State {
             pipeline,
             vertex_buffer,
             vertex_count,
-            msaa_texture_view,
+            render_target,
+            capture_requested: false,
         }
     }
 
     pub fn window(&self) -> &Window {
-        &self.init.window
+        self.init.window()
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.init.is_device_lost()
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
-            // The surface needs to be reconfigured every time the window is resized.
-            self.init.config.width = width;
-            self.init.config.height = height;
-            self.init
-                .surface
-                .configure(&self.init.device, &self.init.config);
-            if self.init.sample_count > 1 {
-                self.msaa_texture_view = ws::create_msaa_texture_view(&self.init);
-            }
+            self.init.resize(width, height);
+            self.render_target.resize(&self.init);
         }
     }
 
@@ -112,6 +126,9 @@ impl State {
             (KeyCode::Escape, true) => {
                 event_loop.exit();
             }
+            (KeyCode::F12, true) => {
+                self.capture_requested = true;
+            }
             _ => {}
         }
     }
@@ -119,34 +136,15 @@ impl State {
     pub fn update(&mut self) {}
 
     pub fn render(&mut self) -> anyhow::Result<()> {
-        let output = match self.init.surface.get_current_texture() {
-            wgpu::CurrentSurfaceTexture::Success(surface_texture) => surface_texture,
-            wgpu::CurrentSurfaceTexture::Suboptimal(surface_texture) => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                surface_texture
-            }
-            wgpu::CurrentSurfaceTexture::Timeout
-            | wgpu::CurrentSurfaceTexture::Occluded
-            | wgpu::CurrentSurfaceTexture::Validation => {
-                // Skip this frame
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Outdated => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Lost => {
-                anyhow::bail!("Lost device");
-            }
-        };
+        if self.init.is_device_lost() {
+            anyhow::bail!("Lost device");
+        }
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let Some(output) = self.init.acquire_frame()? else {
+            // Skip this frame
+            return Ok(());
+        };
+        let view = output.create_view();
 
         let mut encoder =
             self.init
@@ -156,13 +154,7 @@ impl State {
                 });
 
         {
-            let color_attach = ws::create_color_attachment(&view);
-            let msaa_attach = ws::create_msaa_color_attachment(&view, &self.msaa_texture_view);
-            let color_attachment = if self.init.sample_count == 1 {
-                color_attach
-            } else {
-                msaa_attach
-            };
+            let color_attachment = self.render_target.color_attachment(&view);
 
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
@@ -179,6 +171,14 @@ impl State {
         }
 
         self.init.queue.submit(std::iter::once(encoder.finish()));
+
+        // read the frame back before it is presented
+        if std::mem::take(&mut self.capture_requested) {
+            match capture::save_screenshot(&self.init, output.texture()) {
+                Ok(path) => println!("Saved {}", path.display()),
+                Err(e) => log::error!("{e}"),
+            }
+        }
         output.present();
 
         Ok(())
@@ -1,22 +1,14 @@
-struct VertexInput {
+#include "vertex_color.wgsl"
+
+struct Input {
     @location(0) pos: vec2f,
     @location(1) color: vec3f,
 };
 
-struct VertexOutput {
-    @builtin(position) position: vec4f,
-    @location(0) color: vec4f,
-};
-
 @vertex
-fn vs_main(in: VertexInput) -> VertexOutput {
-    var out: VertexOutput;
-    out.color = vec4f(in.color, 1.0);
-    out.position = vec4f(in.pos, 0.0, 1.0);
-    return out;
-}
-
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4f {
-    return in.color;
+fn vs_main(in: Input) -> Output {
+    var output: Output;
+    output.Position = vec4f(in.pos, 0.0, 1.0);
+    output.vColor = vec4f(in.color, 1.0);
+    return output;
 }
@@ -9,18 +9,23 @@ use winit::{
 
 use crate::state::Inputs;
 use crate::state::State;
+use wgpu_fundamentals::wgpu_simplified as ws;
 
 pub struct App<'a> {
     state: Option<State>,
+    window: Option<Arc<Window>>,
+    recovery: ws::DeviceRecovery,
     title: &'a str,
-    inputs: Inputs<'static>,
+    inputs: Inputs,
     num_vertices: u32,
 }
 
 impl<'a> App<'a> {
-    pub fn new(title: &'a str, inputs: Inputs<'static>, num_vertices: u32) -> Self {
+    pub fn new(title: &'a str, inputs: Inputs, num_vertices: u32) -> Self {
         Self {
             state: None,
+            window: None,
+            recovery: ws::DeviceRecovery::new(),
             title,
             inputs,
             num_vertices,
@@ -33,6 +38,7 @@ impl<'a> ApplicationHandler for App<'a> {
         let window_attributes = Window::default_attributes().with_title(self.title);
 
         let window = Arc::new(event_loop.create_window(window_attributes).unwrap());
+        self.window = Some(window.clone());
 
         self.state = Some(pollster::block_on(async {
             State::new(window.into(), &self.inputs, self.num_vertices).await
@@ -58,7 +64,17 @@ impl<'a> ApplicationHandler for App<'a> {
                 state.resize(size.width, size.height);
             }
             WindowEvent::RedrawRequested => {
-                let _ = state.render();
+                if let Err(e) = state.render() {
+                    log::error!("render failed: {e}");
+                }
+                if state.is_device_lost() {
+                    // Nothing created on the old device can be reused; only its InitWgpu is
+                    // kept, so about_to_wait requests a new device with the same settings.
+                    if let Some(state) = self.state.take() {
+                        self.recovery.start(state.init);
+                    }
+                    return;
+                }
                 // Emits a new redraw requested event.
                 state.window().request_redraw();
 
@@ -77,7 +93,10 @@ impl<'a> ApplicationHandler for App<'a> {
         }
     }
 
-    fn about_to_wait(&mut self, _event_loop: &ActiveEventLoop) {
+    fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+        if let Some(init) = self.recovery.poll(event_loop) {
+            self.state = Some(State::with_init(init, &self.inputs, self.num_vertices));
+        }
         if let Some(state) = &self.state {
             state.window().request_redraw();
         }
@@ -1,32 +1,50 @@
 use std::sync::Arc;
-use wgpu::{IndexFormat, PrimitiveTopology, ShaderSource};
+use wgpu::{IndexFormat, PrimitiveTopology};
 use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
 
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::shader_composer::{ComposedShader, ShaderComposer};
 use wgpu_fundamentals::wgpu_simplified as ws;
 
 pub struct State {
-    init: ws::InitWgpu,
+    pub init: ws::InitWgpu,
     pipeline: wgpu::RenderPipeline,
     num_vertices: u32,
+    capture_requested: bool,
 }
 
-pub struct Inputs<'a> {
-    pub source: ShaderSource<'a>,
+pub struct Inputs {
+    pub shader: ComposedShader,
     pub topology: PrimitiveTopology,
     pub strip_index_format: Option<IndexFormat>,
 }
 
+// Composes a triangle shader with the shared vertex color declarations it includes.
+pub fn compose_shader(name: &str, source: &str) -> ComposedShader {
+    ShaderComposer::new()
+        .add_file(
+            "vertex_color.wgsl",
+            include_str!("../../shaders/vertex_color.wgsl"),
+        )
+        .add_file(name, source)
+        .compose(name)
+        .unwrap_or_else(|e| panic!("{e}"))
+}
+
 impl State {
-    pub async fn new(window: Arc<Window>, inputs: &Inputs<'_>, num_vertices: u32) -> Self {
-        let init = ws::InitWgpu::init_wgpu(window.clone(), 1).await;
+    pub async fn new(window: Arc<Window>, inputs: &Inputs, num_vertices: u32) -> Self {
+        Self::with_init(
+            ws::InitWgpu::init_wgpu(window, 1).await,
+            inputs,
+            num_vertices,
+        )
+    }
 
-        // Load the shaders from disk
-        let shader = init
-            .device
-            .create_shader_module(wgpu::ShaderModuleDescriptor {
-                label: None,
-                source: inputs.source.clone(),
-            });
+    pub fn with_init(init: ws::InitWgpu, inputs: &Inputs, num_vertices: u32) -> Self {
+        let shader = inputs
+            .shader
+            .create_shader_module(&init.device, "Triangle Shader")
+            .unwrap_or_else(|e| panic!("{e}"));
 
         let pipeline_layout = init
             .device
@@ -50,21 +68,21 @@ impl State {
             init,
             pipeline,
             num_vertices,
+            capture_requested: false,
         }
     }
 
     pub fn window(&self) -> &Window {
-        &self.init.window
+        self.init.window()
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.init.is_device_lost()
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
-            // The surface needs to be reconfigured every time the window is resized.
-            self.init.config.width = width;
-            self.init.config.height = height;
-            self.init
-                .surface
-                .configure(&self.init.device, &self.init.config);
+            self.init.resize(width, height);
         }
     }
 
@@ -73,6 +91,9 @@ impl State {
             (KeyCode::Escape, true) => {
                 event_loop.exit();
             }
+            (KeyCode::F12, true) => {
+                self.capture_requested = true;
+            }
             _ => {}
         }
     }
@@ -82,34 +103,15 @@ impl State {
     }
 
     pub fn render(&mut self) -> anyhow::Result<()> {
-        let output = match self.init.surface.get_current_texture() {
-            wgpu::CurrentSurfaceTexture::Success(surface_texture) => surface_texture,
-            wgpu::CurrentSurfaceTexture::Suboptimal(surface_texture) => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                surface_texture
-            }
-            wgpu::CurrentSurfaceTexture::Timeout
-            | wgpu::CurrentSurfaceTexture::Occluded
-            | wgpu::CurrentSurfaceTexture::Validation => {
-                // Skip this frame
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Outdated => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Lost => {
-                anyhow::bail!("Lost device");
-            }
-        };
+        if self.init.is_device_lost() {
+            anyhow::bail!("Lost device");
+        }
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let Some(output) = self.init.acquire_frame()? else {
+            // Skip this frame
+            return Ok(());
+        };
+        let view = output.create_view();
 
         let mut encoder =
             self.init
@@ -137,6 +139,14 @@ impl State {
         // Tell the wgpu to finish the command buffer and send it to the
         // GPU's render queue
         self.init.queue.submit(std::iter::once(encoder.finish()));
+
+        // read the frame back before it is presented
+        if std::mem::take(&mut self.capture_requested) {
+            match capture::save_screenshot(&self.init, output.texture()) {
+                Ok(path) => println!("Saved {}", path.display()),
+                Err(e) => log::error!("{e}"),
+            }
+        }
         output.present();
 
         Ok(())
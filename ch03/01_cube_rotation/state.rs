@@ -1,34 +1,96 @@
 use glam::Mat4;
 use std::mem;
+use std::path::Path;
 use std::sync::Arc;
 use wgpu::util::DeviceExt;
 use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
 
 use crate::vertex::{Vertex, create_vertices};
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::hot_reload::{self, HotShader};
+use wgpu_fundamentals::shader_composer::ShaderComposer;
+use wgpu_fundamentals::uniform::UniformBuffer;
 use wgpu_fundamentals::wgpu_simplified as ws;
 
+const SHADER_PATH: &str = concat!(
+    env!("CARGO_MANIFEST_DIR"),
+    "/ch03/01_cube_rotation/unlit_vertex_color.wgsl"
+);
+const INCLUDE_PATHS: [(&str, &str); 2] = [
+    (
+        "mvp.wgsl",
+        concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/mvp.wgsl"),
+    ),
+    (
+        "vertex_color.wgsl",
+        concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/vertex_color.wgsl"),
+    ),
+];
+const CLEAR_COLORS: [wgpu::Color; 4] = [
+    wgpu::Color::BLACK,
+    wgpu::Color {
+        r: 0.05,
+        g: 0.05,
+        b: 0.2,
+        a: 1.0,
+    },
+    wgpu::Color {
+        r: 0.3,
+        g: 0.3,
+        b: 0.3,
+        a: 1.0,
+    },
+    wgpu::Color::WHITE,
+];
+const VERTEX_ATTRIBUTES: [wgpu::VertexAttribute; 2] =
+    wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3];
+
 pub struct State {
-    init: ws::InitWgpu,
+    pub init: ws::InitWgpu,
     pipeline: wgpu::RenderPipeline,
+    pipeline_layout: wgpu::PipelineLayout,
+    shader: wgpu::ShaderModule,
+    hot_shader: Option<HotShader>,
     vertex_buffer: wgpu::Buffer,
     index_buffer: wgpu::Buffer,
     uniform_bind_group: wgpu::BindGroup,
-    uniform_buffer: wgpu::Buffer,
+    uniform_buffer: UniformBuffer<Mat4>,
     view_mat: Mat4,
     project_mat: Mat4,
-    msaa_texture_view: wgpu::TextureView,
+    render_target: ws::RenderTarget,
     indices_len: u32,
-    depth_texture_view: wgpu::TextureView,
     rotation_speed: f32,
+    fps_counter: Option<ws::FpsCounter>,
+    capture_requested: bool,
 }
 
 impl State {
     pub async fn new(window: Arc<Window>, sample_count: u32) -> Self {
-        let init = ws::InitWgpu::init_wgpu(window, sample_count).await;
+        Self::with_init(ws::InitWgpu::init_wgpu(window, sample_count).await)
+    }
 
-        let shader = init
-            .device
-            .create_shader_module(wgpu::include_wgsl!("unlit_vertex_color.wgsl"));
+    pub fn with_init(init: ws::InitWgpu) -> Self {
+        let composed = shader_includes()
+            .add_file(
+                "unlit_vertex_color.wgsl",
+                include_str!("unlit_vertex_color.wgsl"),
+            )
+            .compose("unlit_vertex_color.wgsl")
+            .unwrap_or_else(|e| panic!("{e}"));
+
+        // With WGSL_HOT_RELOAD set, the shader and its includes are read from disk and reloaded
+        // when one of them changes.
+        let hot_shader = std::env::var_os("WGSL_HOT_RELOAD").map(|_| {
+            let include_files = INCLUDE_PATHS.map(|(name, path)| (name, Path::new(path)));
+            HotShader::load(&init, SHADER_PATH, &include_files, ShaderComposer::new())
+                .unwrap_or_else(|e| panic!("{e}"))
+        });
+        let shader = match &hot_shader {
+            Some(hot_shader) => hot_shader.module.clone(),
+            None => composed
+                .create_shader_module(&init.device, "unlit_vertex_color.wgsl")
+                .unwrap_or_else(|e| panic!("{e}")),
+        };
 
         // uniform data
         let camera_position = (3.0, 1.5, 3.0).into();
@@ -44,19 +106,12 @@ impl State {
         );
         let mvp_mat = vp_mat * model_mat;
 
-        let mvp_ref: &[f32; 16] = mvp_mat.as_ref();
-        let uniform_buffer = init
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Uniform Buffer"),
-                contents: bytemuck::cast_slice(mvp_ref),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
+        let uniform_buffer = UniformBuffer::new(&init.device, &mvp_mat);
 
         let (layout, uniform_bind_group) = ws::create_bind_group(
             &init.device,
             vec![wgpu::ShaderStages::VERTEX],
-            &[uniform_buffer.as_entire_binding()],
+            &[uniform_buffer.binding()],
         );
 
         let pipeline_layout = init
@@ -67,22 +122,19 @@ impl State {
                 immediate_size: 0,
             });
 
-        let vertex_buffer_layout = wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+        // catch a layout that doesn't match the shader here, with a readable message
+        let reflection = match &hot_shader {
+            Some(hot_shader) => &hot_shader.reflection,
+            None => &composed.reflect().unwrap_or_else(|e| panic!("{e}")),
         };
+        reflection
+            .validate_vertex_layouts("vs_main", &[vertex_buffer_layout()])
+            .unwrap_or_else(|e| panic!("{e}"));
 
-        let mut ppl = ws::IRenderPipeline {
-            shader: Some(&shader),
-            pipeline_layout: Some(&pipeline_layout),
-            vertex_buffer_layout: &[vertex_buffer_layout],
-            ..Default::default()
-        };
-        let pipeline = ppl.new(&init);
+        let pipeline =
+            create_pipeline(&init, &shader, &pipeline_layout).unwrap_or_else(|e| panic!("{e}"));
 
-        let msaa_texture_view = ws::create_msaa_texture_view(&init);
-        let depth_texture_view = ws::create_depth_view(&init);
+        let render_target = ws::RenderTarget::new(&init);
 
         let (vertex_data, index_data) = create_vertices();
         let vertex_buffer = init
@@ -104,37 +156,37 @@ impl State {
         Self {
             init,
             pipeline,
+            pipeline_layout,
+            shader,
+            hot_shader,
             vertex_buffer,
             index_buffer,
             uniform_bind_group,
             uniform_buffer,
             view_mat,
             project_mat,
-            msaa_texture_view,
+            render_target,
             indices_len: index_data.len() as u32,
-            depth_texture_view,
             rotation_speed: 1.0,
+            fps_counter: None,
+            capture_requested: false,
         }
     }
 
     pub fn window(&self) -> &Window {
-        &self.init.window
+        self.init.window()
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.init.is_device_lost()
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
-            // The surface needs to be reconfigured every time the window is resized.
-            self.init.config.width = width;
-            self.init.config.height = height;
-            self.init
-                .surface
-                .configure(&self.init.device, &self.init.config);
+            self.init.resize(width, height);
 
             self.project_mat = ws::create_projection_mat(width as f32 / height as f32, true);
-            self.depth_texture_view = ws::create_depth_view(&self.init);
-            if self.init.sample_count > 1 {
-                self.msaa_texture_view = ws::create_msaa_texture_view(&self.init);
-            }
+            self.render_target.resize(&self.init);
         }
     }
 
@@ -152,6 +204,40 @@ impl State {
                     self.rotation_speed = 0.0;
                 }
             }
+            (KeyCode::KeyV, true) => {
+                // Switch present mode and start printing the frame rate.
+                let present_mode = self.init.cycle_present_mode();
+                println!("Present mode: {:?}", present_mode);
+                self.fps_counter.get_or_insert_with(ws::FpsCounter::new);
+            }
+            (KeyCode::KeyC, true) => {
+                let next = CLEAR_COLORS
+                    .iter()
+                    .position(|color| *color == self.render_target.clear_color)
+                    .map_or(0, |i| (i + 1) % CLEAR_COLORS.len());
+                self.render_target.clear_color = CLEAR_COLORS[next];
+            }
+            (KeyCode::KeyM, true) => {
+                // The pipeline's multisample state has to match the new attachments. If it
+                // can't be rebuilt, go back to the old count so the old pipeline still fits.
+                let old_sample_count = self.render_target.sample_count;
+                let sample_count = self.render_target.cycle_sample_count(&mut self.init);
+                match create_pipeline(&self.init, &self.shader, &self.pipeline_layout) {
+                    Ok(pipeline) => {
+                        self.pipeline = pipeline;
+                        println!("Sample count: {}", sample_count);
+                    }
+                    Err(e) => {
+                        log::error!("{e}");
+                        let _ = self
+                            .render_target
+                            .set_sample_count(&mut self.init, old_sample_count);
+                    }
+                }
+            }
+            (KeyCode::F12, true) => {
+                self.capture_requested = true;
+            }
             _ => {}
         }
     }
@@ -162,41 +248,45 @@ impl State {
         let model_mat =
             ws::create_model_mat([0.0, 0.0, 0.0], [dt.sin(), dt.cos(), 0.0], [1.0, 1.0, 1.0]);
         let mvp_mat = self.project_mat * self.view_mat * model_mat;
-        let mvp_ref: &[f32; 16] = mvp_mat.as_ref();
-        self.init
-            .queue
-            .write_buffer(&self.uniform_buffer, 0, bytemuck::cast_slice(mvp_ref));
+        self.uniform_buffer.write(&self.init.queue, &mvp_mat);
+
+        if let Some(fps_counter) = &mut self.fps_counter {
+            fps_counter.print_fps(1);
+        }
+
+        // The old pipeline stays in use when the edited shader doesn't build or no longer matches
+        // the vertex buffer layout.
+        if let Some(hot_shader) = &mut self.hot_shader
+            && hot_shader.reload_if_changed(&self.init)
+        {
+            let pipeline = hot_shader
+                .reflection
+                .validate_vertex_layouts("vs_main", &[vertex_buffer_layout()])
+                .map_err(anyhow::Error::from)
+                .and_then(|_| {
+                    create_pipeline(&self.init, &hot_shader.module, &self.pipeline_layout)
+                        .map_err(anyhow::Error::from)
+                });
+            match pipeline {
+                Ok(pipeline) => {
+                    self.pipeline = pipeline;
+                    self.shader = hot_shader.module.clone();
+                }
+                Err(e) => log::error!("{e}"),
+            }
+        }
     }
 
     pub fn render(&mut self) -> anyhow::Result<()> {
-        let output = match self.init.surface.get_current_texture() {
-            wgpu::CurrentSurfaceTexture::Success(surface_texture) => surface_texture,
-            wgpu::CurrentSurfaceTexture::Suboptimal(surface_texture) => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                surface_texture
-            }
-            wgpu::CurrentSurfaceTexture::Timeout
-            | wgpu::CurrentSurfaceTexture::Occluded
-            | wgpu::CurrentSurfaceTexture::Validation => {
-                // Skip this frame
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Outdated => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Lost => {
-                anyhow::bail!("Lost device");
-            }
-        };
+        if self.init.is_device_lost() {
+            anyhow::bail!("Lost device");
+        }
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let Some(output) = self.init.acquire_frame()? else {
+            // Skip this frame
+            return Ok(());
+        };
+        let view = output.create_view();
 
         let mut encoder =
             self.init
@@ -206,19 +296,12 @@ impl State {
                 });
 
         {
-            let color_attach = ws::create_color_attachment(&view);
-            let msaa_attach = ws::create_msaa_color_attachment(&view, &self.msaa_texture_view);
-            let color_attachment = if self.init.sample_count == 1 {
-                color_attach
-            } else {
-                msaa_attach
-            };
-            let depth_attachment = ws::create_depth_stencil_attachment(&self.depth_texture_view);
+            let color_attachment = self.render_target.color_attachment(&view);
 
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(color_attachment)],
-                depth_stencil_attachment: Some(depth_attachment),
+                depth_stencil_attachment: self.render_target.depth_stencil_attachment(),
                 occlusion_query_set: None,
                 timestamp_writes: None,
                 multiview_mask: None,
@@ -232,8 +315,50 @@ impl State {
         }
 
         self.init.queue.submit(std::iter::once(encoder.finish()));
+
+        // read the frame back before it is presented
+        if std::mem::take(&mut self.capture_requested) {
+            match capture::save_screenshot(&self.init, output.texture()) {
+                Ok(path) => println!("Saved {}", path.display()),
+                Err(e) => log::error!("{e}"),
+            }
+        }
         output.present();
 
         Ok(())
     }
 }
+
+fn vertex_buffer_layout() -> wgpu::VertexBufferLayout<'static> {
+    wgpu::VertexBufferLayout {
+        array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+        step_mode: wgpu::VertexStepMode::Vertex,
+        attributes: &VERTEX_ATTRIBUTES,
+    }
+}
+
+// The shared declarations unlit_vertex_color.wgsl includes.
+fn shader_includes() -> ShaderComposer {
+    let mut composer = ShaderComposer::new();
+    composer
+        .add_file("mvp.wgsl", include_str!("../../shaders/mvp.wgsl"))
+        .add_file(
+            "vertex_color.wgsl",
+            include_str!("../../shaders/vertex_color.wgsl"),
+        );
+    composer
+}
+
+fn create_pipeline(
+    init: &ws::InitWgpu,
+    shader: &wgpu::ShaderModule,
+    pipeline_layout: &wgpu::PipelineLayout,
+) -> Result<wgpu::RenderPipeline, wgpu::Error> {
+    let mut ppl = ws::IRenderPipeline {
+        shader: Some(shader),
+        pipeline_layout: Some(pipeline_layout),
+        vertex_buffer_layout: &[vertex_buffer_layout()],
+        ..Default::default()
+    };
+    hot_reload::try_create_pipeline(init, &mut ppl)
+}
@@ -1,9 +1,5 @@
-@binding(0) @group(0) var<uniform> mvpMatrix : mat4x4f;
-
-struct Output {
-    @builtin(position) Position : vec4f,
-    @location(0) vColor : vec4f,
-};
+#include "mvp.wgsl"
+#include "vertex_color.wgsl"
 
 @vertex
 fn vs_main(@location(0) pos: vec3f, @location(1) color: vec3f) -> Output {
@@ -12,8 +8,3 @@ fn vs_main(@location(0) pos: vec3f, @location(1) color: vec3f) -> Output {
     output.vColor = vec4(color, 1.0);
     return output;
 }
-
-@fragment
-fn fs_main(@location(0) vColor: vec4f) -> @location(0) vec4f {
-    return vColor;
-}
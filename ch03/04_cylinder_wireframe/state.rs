@@ -1,5 +1,5 @@
 use bytemuck::cast_slice;
-use glam::Mat4;
+use glam::{Mat4, Vec3};
 use rand;
 use std::mem;
 use std::sync::Arc;
@@ -7,19 +7,22 @@ use wgpu::util::DeviceExt;
 use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
 
 use crate::vertex::{Vertex, create_vertices};
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::shader_composer::ShaderComposer;
+use wgpu_fundamentals::uniform::UniformBuffer;
 use wgpu_fundamentals::wgpu_simplified as ws;
 
 pub struct State {
-    init: ws::InitWgpu,
+    pub init: ws::InitWgpu,
     pipelines: [wgpu::RenderPipeline; 2],
     vertex_buffer: wgpu::Buffer,
     index_buffers: [wgpu::Buffer; 2],
     uniform_bind_groups: [wgpu::BindGroup; 2],
-    uniform_buffers: [wgpu::Buffer; 3],
+    uniform_buffer: UniformBuffer<Mat4>,
+    color_buffers: [UniformBuffer<Vec3>; 2],
     view_mat: Mat4,
     project_mat: Mat4,
-    msaa_texture_view: wgpu::TextureView,
-    depth_texture_view: wgpu::TextureView,
+    render_target: ws::RenderTarget,
     indices_lens: [u32; 2],
     plot_type: u32,
 
@@ -30,15 +33,23 @@ pub struct State {
     update_buffers: bool,
     recreate_buffers: bool,
     rotation_speed: f32,
+    capture_requested: bool,
 }
 
 impl State {
     pub async fn new(window: Arc<Window>, sample_count: u32) -> Self {
-        let init = ws::InitWgpu::init_wgpu(window, sample_count).await;
+        Self::with_init(ws::InitWgpu::init_wgpu(window, sample_count).await)
+    }
 
-        let shader = init
-            .device
-            .create_shader_module(wgpu::include_wgsl!("../common/unlit.wgsl"));
+    pub fn with_init(init: ws::InitWgpu) -> Self {
+        let shader_source = ShaderComposer::new()
+            .add_file("mvp.wgsl", include_str!("../../shaders/mvp.wgsl"))
+            .add_file("unlit.wgsl", include_str!("../common/unlit.wgsl"))
+            .compose("unlit.wgsl")
+            .unwrap_or_else(|e| panic!("{e}"));
+        let shader = shader_source
+            .create_shader_module(&init.device, "unlit.wgsl")
+            .unwrap_or_else(|e| panic!("{e}"));
 
         // uniform data
         let camera_position = (3.0, 1.5, 3.0).into();
@@ -54,49 +65,23 @@ impl State {
         );
         let mvp_mat = vp_mat * model_mat;
 
-        let mvp_ref: &[f32; 16] = mvp_mat.as_ref();
-        let uniform_buffer = init
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Uniform Buffer"),
-                contents: bytemuck::cast_slice(mvp_ref),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
+        let uniform_buffer = UniformBuffer::new(&init.device, &mvp_mat);
 
         // color uniform buffer for object and wireframe
-        let color_buffer = init
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Uniform Buffer"),
-                contents: bytemuck::cast_slice([1.0 as f32, 0.0, 0.0].as_ref()),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
-        let color_buffer2 = init
-            .device
-            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
-                label: Some("Uniform Buffer"),
-                contents: bytemuck::cast_slice([1.0 as f32, 1.0, 0.0].as_ref()),
-                usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
-            });
+        let color_buffer = UniformBuffer::new(&init.device, &Vec3::new(1.0, 0.0, 0.0));
+        let color_buffer2 = UniformBuffer::new(&init.device, &Vec3::new(1.0, 1.0, 0.0));
 
-        let (layout, uniform_bind_group) = ws::create_bind_group(
-            &init.device,
-            vec![wgpu::ShaderStages::VERTEX, wgpu::ShaderStages::FRAGMENT],
-            &[
-                uniform_buffer.as_entire_binding(),
-                color_buffer.as_entire_binding(),
-            ],
-        );
+        let (layout, uniform_bind_group) = ws::BindGroupBuilder::new("Uniform Bind Group")
+            .uniform(0, wgpu::ShaderStages::VERTEX, &uniform_buffer.buffer)
+            .uniform(1, wgpu::ShaderStages::FRAGMENT, &color_buffer.buffer)
+            .build(&init.device);
 
-        let (layout2, uniform_bind_group2) = ws::create_bind_group(
-            &init.device,
-            vec![wgpu::ShaderStages::VERTEX, wgpu::ShaderStages::FRAGMENT],
-            &[
-                uniform_buffer.as_entire_binding(),
-                color_buffer2.as_entire_binding(),
-            ],
-        );
+        let (_, uniform_bind_group2) = ws::BindGroupBuilder::new("Uniform Bind Group 2")
+            .uniform(0, wgpu::ShaderStages::VERTEX, &uniform_buffer.buffer)
+            .uniform(1, wgpu::ShaderStages::FRAGMENT, &color_buffer2.buffer)
+            .build(&init.device);
 
+        // both bind groups have the same layout, so one pipeline layout serves both pipelines
         let pipeline_layout = init
             .device
             .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
@@ -111,39 +96,31 @@ impl State {
             attributes: &wgpu::vertex_attr_array![0 => Float32x3],
         };
 
-        let mut ppl = ws::IRenderPipeline {
-            shader: Some(&shader),
-            pipeline_layout: Some(&pipeline_layout),
-            vertex_buffer_layout: &[vertex_buffer_layout],
-            ..Default::default()
-        };
-        let pipeline = ppl.new(&init);
-
-        let pipeline_layout2 =
-            init.device
-                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
-                    label: Some("Render Pipeline Layout 2"),
-                    bind_group_layouts: &[Some(&layout2)],
-                    immediate_size: 0,
-                });
-
-        let vertex_buffer_layout2 = wgpu::VertexBufferLayout {
-            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
-            step_mode: wgpu::VertexStepMode::Vertex,
-            attributes: &wgpu::vertex_attr_array![0 => Float32x3],
-        };
+        // catch a layout that doesn't match the shader here, with a readable message
+        let reflection = shader_source.reflect().unwrap_or_else(|e| panic!("{e}"));
+        reflection
+            .validate_vertex_layouts("vs_main", std::slice::from_ref(&vertex_buffer_layout))
+            .unwrap_or_else(|e| panic!("{e}"));
 
-        let mut ppl2 = ws::IRenderPipeline {
-            topology: wgpu::PrimitiveTopology::LineList,
+        // The shape and the wireframe only differ in topology.
+        let mut pipeline_cache = ws::RenderPipelineCache::new();
+        let vertex_buffer_layouts = [vertex_buffer_layout];
+        let ppl = ws::IRenderPipeline {
             shader: Some(&shader),
-            pipeline_layout: Some(&pipeline_layout2),
-            vertex_buffer_layout: &[vertex_buffer_layout2],
+            pipeline_layout: Some(&pipeline_layout),
+            vertex_buffer_layout: &vertex_buffer_layouts,
             ..Default::default()
         };
-        let pipeline2 = ppl2.new(&init);
+        let pipeline = pipeline_cache.get_or_create(&init, &ppl);
+        let pipeline2 = pipeline_cache.get_or_create(
+            &init,
+            &ws::IRenderPipeline {
+                topology: wgpu::PrimitiveTopology::LineList,
+                ..ppl
+            },
+        );
 
-        let msaa_texture_view = ws::create_msaa_texture_view(&init);
-        let depth_texture_view = ws::create_depth_view(&init);
+        let render_target = ws::RenderTarget::new(&init);
 
         let (vertex_data, index_data, index_data2) = create_vertices(0.5, 1.5, 3.0, 30);
         let vertex_buffer = init
@@ -176,11 +153,11 @@ impl State {
             vertex_buffer,
             index_buffers: [index_buffer, index_buffer2],
             uniform_bind_groups: [uniform_bind_group, uniform_bind_group2],
-            uniform_buffers: [uniform_buffer, color_buffer, color_buffer2],
+            uniform_buffer,
+            color_buffers: [color_buffer, color_buffer2],
             view_mat,
             project_mat,
-            msaa_texture_view,
-            depth_texture_view,
+            render_target,
             indices_lens: [index_data.len() as u32, index_data2.len() as u32],
             plot_type: 0,
 
@@ -191,27 +168,24 @@ impl State {
             update_buffers: false,
             recreate_buffers: false,
             rotation_speed: 1.0,
+            capture_requested: false,
         }
     }
 
     pub fn window(&self) -> &Window {
-        &self.init.window
+        self.init.window()
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.init.is_device_lost()
     }
 
     pub fn resize(&mut self, width: u32, height: u32) {
         if width > 0 && height > 0 {
-            // The surface needs to be reconfigured every time the window is resized.
-            self.init.config.width = width;
-            self.init.config.height = height;
-            self.init
-                .surface
-                .configure(&self.init.device, &self.init.config);
+            self.init.resize(width, height);
 
             self.project_mat = ws::create_projection_mat(width as f32 / height as f32, true);
-            self.depth_texture_view = ws::create_depth_view(&self.init);
-            if self.init.sample_count > 1 {
-                self.msaa_texture_view = ws::create_msaa_texture_view(&self.init);
-            }
+            self.render_target.resize(&self.init);
         }
     }
 
@@ -221,20 +195,12 @@ impl State {
                 event_loop.exit();
             }
             (KeyCode::ControlLeft, _pressed) => {
-                let scolor: [f32; 3] = [rand::random(), rand::random(), rand::random()];
-                self.init.queue.write_buffer(
-                    &self.uniform_buffers[1],
-                    0,
-                    bytemuck::cast_slice(scolor.as_ref()),
-                );
+                let scolor = Vec3::new(rand::random(), rand::random(), rand::random());
+                self.color_buffers[0].write(&self.init.queue, &scolor);
             }
             (KeyCode::AltLeft, _pressed) => {
-                let wcolor: [f32; 3] = [rand::random(), rand::random(), rand::random()];
-                self.init.queue.write_buffer(
-                    &self.uniform_buffers[2],
-                    0,
-                    bytemuck::cast_slice(wcolor.as_ref()),
-                );
+                let wcolor = Vec3::new(rand::random(), rand::random(), rand::random());
+                self.color_buffers[1].write(&self.init.queue, &wcolor);
             }
             (KeyCode::Space, _pressed) => {
                 self.plot_type = (self.plot_type + 1) % 3;
@@ -280,6 +246,9 @@ impl State {
                     self.rotation_speed = 0.0;
                 }
             }
+            (KeyCode::F12, true) => {
+                self.capture_requested = true;
+            }
             _ => {}
         }
     }
@@ -290,10 +259,7 @@ impl State {
         let model_mat =
             ws::create_model_mat([0.0, 0.0, 0.0], [dt.sin(), dt.cos(), 0.0], [1.0, 1.0, 1.0]);
         let mvp_mat = self.project_mat * self.view_mat * model_mat;
-        let mvp_ref: &[f32; 16] = mvp_mat.as_ref();
-        self.init
-            .queue
-            .write_buffer(&self.uniform_buffers[0], 0, bytemuck::cast_slice(mvp_ref));
+        self.uniform_buffer.write(&self.init.queue, &mvp_mat);
 
         // update vertex buffer
         if self.update_buffers {
@@ -336,34 +302,15 @@ impl State {
     }
 
     pub fn render(&mut self) -> anyhow::Result<()> {
-        let output = match self.init.surface.get_current_texture() {
-            wgpu::CurrentSurfaceTexture::Success(surface_texture) => surface_texture,
-            wgpu::CurrentSurfaceTexture::Suboptimal(surface_texture) => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                surface_texture
-            }
-            wgpu::CurrentSurfaceTexture::Timeout
-            | wgpu::CurrentSurfaceTexture::Occluded
-            | wgpu::CurrentSurfaceTexture::Validation => {
-                // Skip this frame
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Outdated => {
-                self.init
-                    .surface
-                    .configure(&self.init.device, &self.init.config);
-                return Ok(());
-            }
-            wgpu::CurrentSurfaceTexture::Lost => {
-                anyhow::bail!("Lost device");
-            }
-        };
+        if self.init.is_device_lost() {
+            anyhow::bail!("Lost device");
+        }
 
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        let Some(output) = self.init.acquire_frame()? else {
+            // Skip this frame
+            return Ok(());
+        };
+        let view = output.create_view();
 
         let mut encoder =
             self.init
@@ -373,19 +320,12 @@ impl State {
                 });
 
         {
-            let color_attach = ws::create_color_attachment(&view);
-            let msaa_attach = ws::create_msaa_color_attachment(&view, &self.msaa_texture_view);
-            let color_attachment = if self.init.sample_count == 1 {
-                color_attach
-            } else {
-                msaa_attach
-            };
-            let depth_attachment = ws::create_depth_stencil_attachment(&self.depth_texture_view);
+            let color_attachment = self.render_target.color_attachment(&view);
 
             let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
                 label: Some("Render Pass"),
                 color_attachments: &[Some(color_attachment)],
-                depth_stencil_attachment: Some(depth_attachment),
+                depth_stencil_attachment: self.render_target.depth_stencil_attachment(),
                 occlusion_query_set: None,
                 timestamp_writes: None,
                 multiview_mask: None,
@@ -419,6 +359,14 @@ impl State {
         }
 
         self.init.queue.submit(std::iter::once(encoder.finish()));
+
+        // read the frame back before it is presented
+        if std::mem::take(&mut self.capture_requested) {
+            match capture::save_screenshot(&self.init, output.texture()) {
+                Ok(path) => println!("Saved {}", path.display()),
+                Err(e) => log::error!("{e}"),
+            }
+        }
         output.present();
 
         Ok(())
@@ -0,0 +1,17 @@
+struct Object {
+    mvpMatrix: mat4x4f,
+    color: vec3f,
+};
+
+// bound with a dynamic offset, one Object per draw
+@binding(0) @group(0) var<uniform> object: Object;
+
+@vertex
+fn vs_main(@location(0) pos: vec3f) -> @builtin(position) vec4f {
+    return object.mvpMatrix * vec4(pos, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return vec4(object.color, 1.0);
+}
@@ -0,0 +1,301 @@
+use glam::{Mat4, Vec3};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use std::mem;
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
+
+use crate::vertex::{Vertex, create_vertices};
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::uniform::UniformArena;
+use wgpu_fundamentals::wgpu_simplified as ws;
+use wgpu_fundamentals::wgsl_struct;
+
+// The cubes come from a seeded generator, so every run (and the golden test) shows the same
+// scene.
+const SEED: u64 = 1;
+
+wgsl_struct! {
+    struct ObjectUniforms {
+        mvp_mat: Mat4,
+        color: Vec3,
+    }
+}
+
+struct Cube {
+    position: [f32; 3],
+    rotation_axis: [f32; 3],
+    scale: f32,
+    color: Vec3,
+}
+
+impl Cube {
+    fn random(rng: &mut StdRng) -> Self {
+        let mut spread = |range: f32| (rng.random::<f32>() - 0.5) * range;
+        let position = [spread(30.0), spread(20.0), spread(30.0)];
+        Self {
+            position,
+            rotation_axis: rng.random(),
+            scale: 0.2 + 0.4 * rng.random::<f32>(),
+            color: Vec3::from_array(rng.random()),
+        }
+    }
+}
+
+pub struct State {
+    pub init: ws::InitWgpu,
+    pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    uniform_arena: UniformArena<ObjectUniforms>,
+    uniform_bind_group_layout: wgpu::BindGroupLayout,
+    uniform_bind_group: wgpu::BindGroup,
+    offsets: Vec<u32>,
+    cubes: Vec<Cube>,
+    rng: StdRng,
+    view_mat: Mat4,
+    project_mat: Mat4,
+    render_target: ws::RenderTarget,
+    indices_len: u32,
+    rotation_speed: f32,
+    capture_requested: bool,
+}
+
+impl State {
+    pub async fn new(window: Arc<Window>, sample_count: u32) -> Self {
+        Self::with_init(ws::InitWgpu::init_wgpu(window, sample_count).await)
+    }
+
+    pub fn with_init(init: ws::InitWgpu) -> Self {
+        let shader = init
+            .device
+            .create_shader_module(wgpu::include_wgsl!("many_cubes.wgsl"));
+
+        // uniform data
+        let camera_position = (25.0, 15.0, 25.0).into();
+        let look_direction = (0.0, 0.0, 0.0).into();
+        let up_direction = (0.0, 1.0, 0.0).into();
+
+        let (view_mat, project_mat, _) = ws::create_vp_mat(
+            camera_position,
+            look_direction,
+            up_direction,
+            init.config.width as f32 / init.config.height as f32,
+        );
+
+        // all cubes share one buffer and one bind group; each draw selects its slot with a
+        // dynamic offset
+        let mut rng = StdRng::seed_from_u64(SEED);
+        let cubes: Vec<Cube> = (0..500).map(|_| Cube::random(&mut rng)).collect();
+        let uniform_arena = UniformArena::new(&init.device, cubes.len() as u64);
+        let (uniform_bind_group_layout, uniform_bind_group) =
+            uniform_bind_group_builder(&uniform_arena).build(&init.device);
+
+        let pipeline_layout = init
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[Some(&uniform_bind_group_layout)],
+                immediate_size: 0,
+            });
+
+        let vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x3],
+        };
+
+        let mut ppl = ws::IRenderPipeline {
+            shader: Some(&shader),
+            pipeline_layout: Some(&pipeline_layout),
+            vertex_buffer_layout: &[vertex_buffer_layout],
+            ..Default::default()
+        };
+        let pipeline = ppl.new(&init);
+
+        let mut render_target = ws::RenderTarget::new(&init);
+        render_target.clear_color = wgpu::Color {
+            r: 0.05,
+            g: 0.05,
+            b: 0.1,
+            a: 1.0,
+        };
+
+        let (vertex_data, index_data, _) = create_vertices();
+        let vertex_buffer = init
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertex_data),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        let index_buffer = init
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(&index_data),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
+        Self {
+            init,
+            pipeline,
+            vertex_buffer,
+            index_buffer,
+            uniform_arena,
+            uniform_bind_group_layout,
+            uniform_bind_group,
+            offsets: vec![],
+            cubes,
+            rng,
+            view_mat,
+            project_mat,
+            render_target,
+            indices_len: index_data.len() as u32,
+            rotation_speed: 1.0,
+            capture_requested: false,
+        }
+    }
+
+    pub fn window(&self) -> &Window {
+        self.init.window()
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.init.is_device_lost()
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.init.resize(width, height);
+
+            self.project_mat = ws::create_projection_mat(width as f32 / height as f32, true);
+            self.render_target.resize(&self.init);
+        }
+    }
+
+    pub fn handle_key_input(&mut self, event_loop: &ActiveEventLoop, key: KeyCode, pressed: bool) {
+        match (key, pressed) {
+            (KeyCode::Escape, true) => {
+                event_loop.exit();
+            }
+            (KeyCode::Space, true) => {
+                // more cubes than the arena holds makes it grow
+                self.cubes
+                    .extend((0..100).map(|_| Cube::random(&mut self.rng)));
+                println!("Cubes: {}", self.cubes.len());
+            }
+            (KeyCode::Backspace, true) => {
+                self.cubes.truncate(self.cubes.len().saturating_sub(100));
+                println!("Cubes: {}", self.cubes.len());
+            }
+            (KeyCode::KeyQ, _pressed) => {
+                self.rotation_speed += 0.1;
+            }
+            (KeyCode::KeyA, _pressed) => {
+                self.rotation_speed -= 0.1;
+                if self.rotation_speed < 0.0 {
+                    self.rotation_speed = 0.0;
+                }
+            }
+            (KeyCode::F12, true) => {
+                self.capture_requested = true;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, dt: std::time::Duration) {
+        // update uniform arena
+        let dt = self.rotation_speed * dt.as_secs_f32();
+        let vp_mat = self.project_mat * self.view_mat;
+
+        self.uniform_arena.clear();
+        self.offsets.clear();
+        for cube in &self.cubes {
+            let rotation = cube.rotation_axis.map(|a| a * dt);
+            let model_mat = ws::create_model_mat(cube.position, rotation, [cube.scale; 3]);
+            let offset = self.uniform_arena.push(&ObjectUniforms {
+                mvp_mat: vp_mat * model_mat,
+                color: cube.color,
+            });
+            self.offsets.push(offset);
+        }
+
+        if self
+            .uniform_arena
+            .upload(&self.init.device, &self.init.queue)
+        {
+            // the arena moved to a larger buffer
+            self.uniform_bind_group = uniform_bind_group_builder(&self.uniform_arena)
+                .build_with_layout(&self.init.device, &self.uniform_bind_group_layout);
+        }
+    }
+
+    pub fn render(&mut self) -> anyhow::Result<()> {
+        if self.init.is_device_lost() {
+            anyhow::bail!("Lost device");
+        }
+
+        let Some(output) = self.init.acquire_frame()? else {
+            // Skip this frame
+            return Ok(());
+        };
+        let view = output.create_view();
+
+        let mut encoder =
+            self.init
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+
+        {
+            let color_attachment = self.render_target.color_attachment(&view);
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(color_attachment)],
+                depth_stencil_attachment: self.render_target.depth_stencil_attachment(),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+                multiview_mask: None,
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            for offset in &self.offsets {
+                render_pass.set_bind_group(0, &self.uniform_bind_group, &[*offset]);
+                render_pass.draw_indexed(0..self.indices_len, 0, 0..1);
+            }
+        }
+
+        self.init.queue.submit(std::iter::once(encoder.finish()));
+
+        // read the frame back before it is presented
+        if std::mem::take(&mut self.capture_requested) {
+            match capture::save_screenshot(&self.init, output.texture()) {
+                Ok(path) => println!("Saved {}", path.display()),
+                Err(e) => log::error!("{e}"),
+            }
+        }
+        output.present();
+
+        Ok(())
+    }
+}
+
+fn uniform_bind_group_builder(
+    uniform_arena: &UniformArena<ObjectUniforms>,
+) -> ws::BindGroupBuilder<'_> {
+    ws::BindGroupBuilder::new("Uniform Bind Group").buffer(
+        0,
+        wgpu::ShaderStages::VERTEX_FRAGMENT,
+        wgpu::BufferBindingType::Uniform,
+        true,
+        uniform_arena.binding(),
+    )
+}
@@ -0,0 +1,30 @@
+use app::App;
+use winit::event_loop::EventLoop;
+
+#[path = "../common/app.rs"]
+mod app;
+
+mod state;
+#[path = "../01_cube_rotation/vertex.rs"]
+mod vertex;
+
+fn main() {
+    let mut sample_count: u32 = 1;
+    let args: Vec<String> = std::env::args().collect();
+    if args.len() > 1 {
+        sample_count = args[1].parse::<u32>().unwrap();
+    }
+
+    let title = "ch03 picture in picture";
+    let _ = run(title, sample_count);
+
+    pub fn run(title: &'static str, sample_count: u32) -> anyhow::Result<()> {
+        env_logger::init();
+
+        let event_loop = EventLoop::builder().build()?;
+        let mut app = App::new(title, sample_count, None);
+        event_loop.run_app(&mut app)?;
+
+        Ok(())
+    }
+}
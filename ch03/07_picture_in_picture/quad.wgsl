@@ -0,0 +1,22 @@
+@group(0) @binding(0) var inset: texture_2d<f32>;
+@group(0) @binding(1) var inset_sampler: sampler;
+
+struct Output {
+    @builtin(position) Position : vec4f,
+    @location(0) vUv : vec2f,
+};
+
+// A quad in the top-right corner, drawn as a 4-vertex triangle strip without vertex buffers.
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> Output {
+    let corner = vec2f(f32(index & 1u), f32(index >> 1u));
+    var output: Output;
+    output.Position = vec4(mix(vec2(0.4, 0.4), vec2(0.95, 0.95), corner), 0.0, 1.0);
+    output.vUv = vec2(corner.x, 1.0 - corner.y);
+    return output;
+}
+
+@fragment
+fn fs_main(@location(0) vUv: vec2f) -> @location(0) vec4f {
+    return textureSample(inset, inset_sampler, vUv);
+}
@@ -0,0 +1,333 @@
+use glam::Mat4;
+use std::mem;
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
+
+use crate::vertex::{Vertex, create_vertices};
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::shader_composer::ShaderComposer;
+use wgpu_fundamentals::uniform::UniformBuffer;
+use wgpu_fundamentals::wgpu_simplified as ws;
+
+pub struct State {
+    pub init: ws::InitWgpu,
+    pipeline: wgpu::RenderPipeline,
+    inset_pipeline: wgpu::RenderPipeline,
+    quad_pipeline: wgpu::RenderPipeline,
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    uniform_bind_group: wgpu::BindGroup,
+    uniform_buffer: UniformBuffer<Mat4>,
+    inset_bind_group: wgpu::BindGroup,
+    inset_buffer: UniformBuffer<Mat4>,
+    quad_bind_group: wgpu::BindGroup,
+    view_mat: Mat4,
+    project_mat: Mat4,
+    inset_vp_mat: Mat4,
+    render_target: ws::RenderTarget,
+    render_texture: ws::RenderTexture,
+    indices_len: u32,
+    rotation_speed: f32,
+    capture_requested: bool,
+}
+
+impl State {
+    pub async fn new(window: Arc<Window>, sample_count: u32) -> Self {
+        Self::with_init(ws::InitWgpu::init_wgpu(window, sample_count).await)
+    }
+
+    pub fn with_init(init: ws::InitWgpu) -> Self {
+        let shader = ShaderComposer::new()
+            .add_file("mvp.wgsl", include_str!("../../shaders/mvp.wgsl"))
+            .add_file(
+                "vertex_color.wgsl",
+                include_str!("../../shaders/vertex_color.wgsl"),
+            )
+            .add_file(
+                "unlit_vertex_color.wgsl",
+                include_str!("../01_cube_rotation/unlit_vertex_color.wgsl"),
+            )
+            .compose("unlit_vertex_color.wgsl")
+            .and_then(|composed| {
+                composed.create_shader_module(&init.device, "unlit_vertex_color.wgsl")
+            })
+            .unwrap_or_else(|e| panic!("{e}"));
+        let quad_shader = init
+            .device
+            .create_shader_module(wgpu::include_wgsl!("quad.wgsl"));
+
+        // the main camera looks at the cube from the side, the inset camera from above
+        let (view_mat, project_mat, _) = ws::create_vp_mat(
+            (3.0, 1.5, 3.0).into(),
+            (0.0, 0.0, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+            init.config.width as f32 / init.config.height as f32,
+        );
+        let (_, _, inset_vp_mat) = ws::create_vp_mat(
+            (0.0, 5.0, 0.0).into(),
+            (0.0, 0.0, 0.0).into(),
+            (0.0, 0.0, -1.0).into(),
+            1.0,
+        );
+
+        let uniform_buffer = UniformBuffer::new(&init.device, &Mat4::IDENTITY);
+        let inset_buffer = UniformBuffer::new(&init.device, &Mat4::IDENTITY);
+
+        let (layout, uniform_bind_group) = ws::BindGroupBuilder::new("Uniform Bind Group")
+            .uniform(0, wgpu::ShaderStages::VERTEX, &uniform_buffer.buffer)
+            .build(&init.device);
+        let inset_bind_group = ws::BindGroupBuilder::new("Inset Bind Group")
+            .uniform(0, wgpu::ShaderStages::VERTEX, &inset_buffer.buffer)
+            .build_with_layout(&init.device, &layout);
+
+        let pipeline_layout = init
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[Some(&layout)],
+                immediate_size: 0,
+            });
+
+        // the scene seen by the inset camera is rendered into a texture first
+        let mut render_texture = ws::IRenderTexture {
+            label: "Inset Texture",
+            width: 512,
+            height: 512,
+            depth_format: Some(init.depth_format),
+            ..Default::default()
+        }
+        .build(&init);
+        render_texture.clear_color = wgpu::Color {
+            r: 0.2,
+            g: 0.2,
+            b: 0.3,
+            a: 1.0,
+        };
+
+        let (quad_layout, quad_bind_group) = ws::BindGroupBuilder::new("Quad Bind Group")
+            .texture(
+                0,
+                wgpu::ShaderStages::FRAGMENT,
+                &render_texture.view,
+                wgpu::TextureSampleType::Float { filterable: true },
+                wgpu::TextureViewDimension::D2,
+            )
+            .sampler(
+                1,
+                wgpu::ShaderStages::FRAGMENT,
+                &render_texture.sampler,
+                wgpu::SamplerBindingType::Filtering,
+            )
+            .build(&init.device);
+
+        let quad_pipeline_layout =
+            init.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Quad Pipeline Layout"),
+                    bind_group_layouts: &[Some(&quad_layout)],
+                    immediate_size: 0,
+                });
+
+        let vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+        };
+
+        let mut ppl = ws::IRenderPipeline {
+            shader: Some(&shader),
+            pipeline_layout: Some(&pipeline_layout),
+            vertex_buffer_layout: std::slice::from_ref(&vertex_buffer_layout),
+            ..Default::default()
+        };
+        let pipeline = ppl.new(&init);
+
+        let mut ppl = ws::IRenderPipeline {
+            label: "Inset Pipeline",
+            shader: Some(&shader),
+            pipeline_layout: Some(&pipeline_layout),
+            vertex_buffer_layout: &[vertex_buffer_layout],
+            color_targets: &[Some(render_texture.format().into())],
+            sample_count: Some(render_texture.sample_count()),
+            ..Default::default()
+        };
+        let inset_pipeline = ppl.new(&init);
+
+        // the quad is drawn last in the main pass and always on top
+        let mut ppl = ws::IRenderPipeline {
+            label: "Quad Pipeline",
+            shader: Some(&quad_shader),
+            pipeline_layout: Some(&quad_pipeline_layout),
+            topology: wgpu::PrimitiveTopology::TriangleStrip,
+            depth_compare: wgpu::CompareFunction::Always,
+            depth_write_enabled: false,
+            ..Default::default()
+        };
+        let quad_pipeline = ppl.new(&init);
+
+        let render_target = ws::RenderTarget::new(&init);
+
+        let (vertex_data, index_data) = create_vertices();
+        let vertex_buffer = init
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertex_data),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        let index_buffer = init
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(&index_data),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
+        Self {
+            init,
+            pipeline,
+            inset_pipeline,
+            quad_pipeline,
+            vertex_buffer,
+            index_buffer,
+            uniform_bind_group,
+            uniform_buffer,
+            inset_bind_group,
+            inset_buffer,
+            quad_bind_group,
+            view_mat,
+            project_mat,
+            inset_vp_mat,
+            render_target,
+            render_texture,
+            indices_len: index_data.len() as u32,
+            rotation_speed: 1.0,
+            capture_requested: false,
+        }
+    }
+
+    pub fn window(&self) -> &Window {
+        self.init.window()
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.init.is_device_lost()
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.init.resize(width, height);
+
+            self.project_mat = ws::create_projection_mat(width as f32 / height as f32, true);
+            self.render_target.resize(&self.init);
+        }
+    }
+
+    pub fn handle_key_input(&mut self, event_loop: &ActiveEventLoop, key: KeyCode, pressed: bool) {
+        match (key, pressed) {
+            (KeyCode::Escape, true) => {
+                event_loop.exit();
+            }
+            (KeyCode::KeyQ, _pressed) => {
+                self.rotation_speed += 0.1;
+            }
+            (KeyCode::KeyA, _pressed) => {
+                self.rotation_speed -= 0.1;
+                if self.rotation_speed < 0.0 {
+                    self.rotation_speed = 0.0;
+                }
+            }
+            (KeyCode::F12, true) => {
+                self.capture_requested = true;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, dt: std::time::Duration) {
+        // update uniform buffers
+        let dt = self.rotation_speed * dt.as_secs_f32();
+        let model_mat =
+            ws::create_model_mat([0.0, 0.0, 0.0], [dt.sin(), dt.cos(), 0.0], [1.0, 1.0, 1.0]);
+        let mvp_mat = self.project_mat * self.view_mat * model_mat;
+        self.uniform_buffer.write(&self.init.queue, &mvp_mat);
+        self.inset_buffer
+            .write(&self.init.queue, &(self.inset_vp_mat * model_mat));
+    }
+
+    pub fn render(&mut self) -> anyhow::Result<()> {
+        if self.init.is_device_lost() {
+            anyhow::bail!("Lost device");
+        }
+
+        let Some(output) = self.init.acquire_frame()? else {
+            // Skip this frame
+            return Ok(());
+        };
+        let view = output.create_view();
+
+        let mut encoder =
+            self.init
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+
+        // inset pass: render the top view into the texture
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Inset Pass"),
+                color_attachments: &[Some(self.render_texture.color_attachment())],
+                depth_stencil_attachment: self.render_texture.depth_stencil_attachment(),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+                multiview_mask: None,
+            });
+
+            render_pass.set_pipeline(&self.inset_pipeline);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.set_bind_group(0, &self.inset_bind_group, &[]);
+            render_pass.draw_indexed(0..self.indices_len, 0, 0..1);
+        }
+
+        // main pass: the scene, then the texture on a quad in the corner
+        {
+            let color_attachment = self.render_target.color_attachment(&view);
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(color_attachment)],
+                depth_stencil_attachment: self.render_target.depth_stencil_attachment(),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+                multiview_mask: None,
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+            render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+            render_pass.set_bind_group(0, &self.uniform_bind_group, &[]);
+            render_pass.draw_indexed(0..self.indices_len, 0, 0..1);
+
+            render_pass.set_pipeline(&self.quad_pipeline);
+            render_pass.set_bind_group(0, &self.quad_bind_group, &[]);
+            render_pass.draw(0..4, 0..1);
+        }
+
+        self.init.queue.submit(std::iter::once(encoder.finish()));
+
+        // read the frame back before it is presented
+        if std::mem::take(&mut self.capture_requested) {
+            match capture::save_screenshot(&self.init, output.texture()) {
+                Ok(path) => println!("Saved {}", path.display()),
+                Err(e) => log::error!("{e}"),
+            }
+        }
+        output.present();
+
+        Ok(())
+    }
+}
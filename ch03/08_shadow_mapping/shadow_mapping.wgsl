@@ -0,0 +1,54 @@
+#include "shadow.wgsl"
+
+struct Scene {
+    vpMatrix: mat4x4f,
+    lightVpMatrix: mat4x4f,
+    // the direction the light travels in
+    lightDirection: vec3f,
+    // 1 for shadow_pcf, 0 for shadow_hard
+    pcf: u32,
+};
+
+struct Mesh {
+    modelMatrix: mat4x4f,
+    normalMatrix: mat4x4f,
+    color: vec3f,
+};
+
+@group(0) @binding(0) var<uniform> scene: Scene;
+@group(1) @binding(0) var<uniform> mesh: Mesh;
+
+struct Input {
+    @location(0) pos: vec3f,
+    @location(1) normal: vec3f,
+};
+
+struct Output {
+    @builtin(position) Position: vec4f,
+    @location(0) vNormal: vec3f,
+    @location(1) vLightPosition: vec4f,
+};
+
+// depth-only pass from the light
+@vertex
+fn vs_shadow(in: Input) -> @builtin(position) vec4f {
+    return scene.lightVpMatrix * mesh.modelMatrix * vec4(in.pos, 1.0);
+}
+
+@vertex
+fn vs_main(in: Input) -> Output {
+    let worldPosition = mesh.modelMatrix * vec4(in.pos, 1.0);
+    var output: Output;
+    output.Position = scene.vpMatrix * worldPosition;
+    output.vNormal = (mesh.normalMatrix * vec4(in.normal, 0.0)).xyz;
+    output.vLightPosition = scene.lightVpMatrix * worldPosition;
+    return output;
+}
+
+@fragment
+fn fs_main(in: Output) -> @location(0) vec4f {
+    let diffuse = max(dot(normalize(in.vNormal), -scene.lightDirection), 0.0);
+    let lit = select(shadow_hard(in.vLightPosition), shadow_pcf(in.vLightPosition), scene.pcf != 0u);
+    let color = mesh.color * (0.2 + 0.8 * diffuse * lit);
+    return vec4(color, 1.0);
+}
@@ -0,0 +1,411 @@
+use glam::{Mat4, Vec3};
+use std::mem;
+use std::sync::Arc;
+use wgpu::util::DeviceExt;
+use winit::{event_loop::ActiveEventLoop, keyboard::KeyCode, window::Window};
+
+use crate::vertex::{Vertex, create_plane_vertices, create_sphere_vertices, create_torus_vertices};
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::shader_composer::ShaderComposer;
+use wgpu_fundamentals::shadow;
+use wgpu_fundamentals::uniform::UniformBuffer;
+use wgpu_fundamentals::wgpu_simplified as ws;
+use wgpu_fundamentals::wgsl_struct;
+
+wgsl_struct! {
+    struct SceneUniforms {
+        vp_mat: Mat4,
+        light_vp_mat: Mat4,
+        light_direction: Vec3,
+        pcf: u32,
+    }
+}
+
+wgsl_struct! {
+    struct MeshUniforms {
+        model_mat: Mat4,
+        normal_mat: Mat4,
+        color: Vec3,
+    }
+}
+
+struct Mesh {
+    vertex_buffer: wgpu::Buffer,
+    index_buffer: wgpu::Buffer,
+    indices_len: u32,
+    uniform_buffer: UniformBuffer<MeshUniforms>,
+    bind_group: wgpu::BindGroup,
+    color: Vec3,
+}
+
+impl Mesh {
+    fn new(
+        init: &ws::InitWgpu,
+        layout: &wgpu::BindGroupLayout,
+        (vertex_data, index_data): (Vec<Vertex>, Vec<u16>),
+        color: Vec3,
+    ) -> Self {
+        let vertex_buffer = init
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Vertex Buffer"),
+                contents: bytemuck::cast_slice(&vertex_data),
+                usage: wgpu::BufferUsages::VERTEX,
+            });
+
+        let index_buffer = init
+            .device
+            .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+                label: Some("Index Buffer"),
+                contents: bytemuck::cast_slice(&index_data),
+                usage: wgpu::BufferUsages::INDEX,
+            });
+
+        let uniform_buffer = UniformBuffer::new(
+            &init.device,
+            &MeshUniforms {
+                model_mat: Mat4::IDENTITY,
+                normal_mat: Mat4::IDENTITY,
+                color,
+            },
+        );
+        let bind_group = ws::BindGroupBuilder::new("Mesh Bind Group")
+            .uniform(
+                0,
+                wgpu::ShaderStages::VERTEX_FRAGMENT,
+                &uniform_buffer.buffer,
+            )
+            .build_with_layout(&init.device, layout);
+
+        Self {
+            vertex_buffer,
+            index_buffer,
+            indices_len: index_data.len() as u32,
+            uniform_buffer,
+            bind_group,
+            color,
+        }
+    }
+
+    fn set_model_mat(&self, queue: &wgpu::Queue, model_mat: Mat4) {
+        self.uniform_buffer.write(
+            queue,
+            &MeshUniforms {
+                model_mat,
+                normal_mat: model_mat.inverse().transpose(),
+                color: self.color,
+            },
+        );
+    }
+
+    fn draw(&self, render_pass: &mut wgpu::RenderPass) {
+        render_pass.set_vertex_buffer(0, self.vertex_buffer.slice(..));
+        render_pass.set_index_buffer(self.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
+        render_pass.set_bind_group(1, &self.bind_group, &[]);
+        render_pass.draw_indexed(0..self.indices_len, 0, 0..1);
+    }
+}
+
+pub struct State {
+    pub init: ws::InitWgpu,
+    pipeline: wgpu::RenderPipeline,
+    shadow_pipeline: wgpu::RenderPipeline,
+    scene_bind_group: wgpu::BindGroup,
+    scene_buffer: UniformBuffer<SceneUniforms>,
+    shadow_bind_group: wgpu::BindGroup,
+    shadow_map: shadow::ShadowMap,
+    // ground plane, torus, sphere
+    meshes: [Mesh; 3],
+    view_mat: Mat4,
+    project_mat: Mat4,
+    light_direction: Vec3,
+    light_vp_mat: Mat4,
+    render_target: ws::RenderTarget,
+    pcf: bool,
+    rotation_speed: f32,
+    capture_requested: bool,
+}
+
+impl State {
+    pub async fn new(window: Arc<Window>, sample_count: u32) -> Self {
+        Self::with_init(ws::InitWgpu::init_wgpu(window, sample_count).await)
+    }
+
+    pub fn with_init(init: ws::InitWgpu) -> Self {
+        // the shadow map and its sampler are bind group 2
+        let shader_source = ShaderComposer::new()
+            .add_file("shadow.wgsl", shadow::SHADOW_WGSL)
+            .add_file("shadow_mapping.wgsl", include_str!("shadow_mapping.wgsl"))
+            .define("SHADOW_GROUP", "2")
+            .compose("shadow_mapping.wgsl")
+            .unwrap_or_else(|e| panic!("{e}"));
+        let shader = shader_source
+            .create_shader_module(&init.device, "shadow_mapping.wgsl")
+            .unwrap_or_else(|e| panic!("{e}"));
+
+        // camera and light
+        let (view_mat, project_mat, _) = ws::create_vp_mat(
+            (3.5, 3.0, 4.5).into(),
+            (0.0, 0.5, 0.0).into(),
+            (0.0, 1.0, 0.0).into(),
+            init.config.width as f32 / init.config.height as f32,
+        );
+        let light_direction = Vec3::new(-1.0, -1.5, -0.3).normalize();
+        let (_, _, light_vp_mat) =
+            shadow::create_light_vp_mat(light_direction, (0.0, 0.0, 0.0).into(), 6.0);
+
+        let shadow_map = shadow::IShadowMap::default().build(&init);
+        let (shadow_layout, shadow_bind_group) =
+            shadow_map.bind_group_builder().build(&init.device);
+
+        let scene_buffer = UniformBuffer::new(
+            &init.device,
+            &SceneUniforms {
+                vp_mat: Mat4::IDENTITY,
+                light_vp_mat,
+                light_direction,
+                pcf: 1,
+            },
+        );
+        let (scene_layout, scene_bind_group) = ws::BindGroupBuilder::new("Scene Bind Group")
+            .uniform(0, wgpu::ShaderStages::VERTEX_FRAGMENT, &scene_buffer.buffer)
+            .build(&init.device);
+
+        // each mesh has its own bind group with this layout
+        let mesh_layout =
+            ws::create_bind_group_layout(&init.device, vec![wgpu::ShaderStages::VERTEX_FRAGMENT]);
+
+        // the shadow pass only runs vs_shadow, which doesn't use the shadow map
+        let shadow_pipeline_layout =
+            init.device
+                .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                    label: Some("Shadow Pipeline Layout"),
+                    bind_group_layouts: &[Some(&scene_layout), Some(&mesh_layout)],
+                    immediate_size: 0,
+                });
+        let pipeline_layout = init
+            .device
+            .create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &[
+                    Some(&scene_layout),
+                    Some(&mesh_layout),
+                    Some(&shadow_layout),
+                ],
+                immediate_size: 0,
+            });
+
+        let vertex_buffer_layout = wgpu::VertexBufferLayout {
+            array_stride: mem::size_of::<Vertex>() as wgpu::BufferAddress,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes: &wgpu::vertex_attr_array![0 => Float32x3, 1 => Float32x3],
+        };
+
+        let mut ppl = ws::IRenderPipeline {
+            vs_shader: Some(&shader),
+            vs_entry: String::from("vs_shadow"),
+            pipeline_layout: Some(&shadow_pipeline_layout),
+            vertex_buffer_layout: std::slice::from_ref(&vertex_buffer_layout),
+            ..shadow_map.pipeline()
+        };
+        let shadow_pipeline = ppl.new(&init);
+
+        let mut ppl = ws::IRenderPipeline {
+            shader: Some(&shader),
+            pipeline_layout: Some(&pipeline_layout),
+            vertex_buffer_layout: &[vertex_buffer_layout],
+            ..Default::default()
+        };
+        let pipeline = ppl.new(&init);
+
+        let mut render_target = ws::RenderTarget::new(&init);
+        render_target.clear_color = wgpu::Color {
+            r: 0.2,
+            g: 0.25,
+            b: 0.3,
+            a: 1.0,
+        };
+
+        let meshes = [
+            Mesh::new(
+                &init,
+                &mesh_layout,
+                create_plane_vertices(12.0),
+                Vec3::new(0.8, 0.8, 0.8),
+            ),
+            Mesh::new(
+                &init,
+                &mesh_layout,
+                create_torus_vertices(1.0, 0.35, 60, 20),
+                Vec3::new(1.0, 0.3, 0.2),
+            ),
+            Mesh::new(
+                &init,
+                &mesh_layout,
+                create_sphere_vertices(0.7, 20, 30),
+                Vec3::new(0.2, 0.5, 1.0),
+            ),
+        ];
+
+        Self {
+            init,
+            pipeline,
+            shadow_pipeline,
+            scene_bind_group,
+            scene_buffer,
+            shadow_bind_group,
+            shadow_map,
+            meshes,
+            view_mat,
+            project_mat,
+            light_direction,
+            light_vp_mat,
+            render_target,
+            pcf: true,
+            rotation_speed: 1.0,
+            capture_requested: false,
+        }
+    }
+
+    pub fn window(&self) -> &Window {
+        self.init.window()
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.init.is_device_lost()
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.init.resize(width, height);
+
+            self.project_mat = ws::create_projection_mat(width as f32 / height as f32, true);
+            self.render_target.resize(&self.init);
+        }
+    }
+
+    pub fn handle_key_input(&mut self, event_loop: &ActiveEventLoop, key: KeyCode, pressed: bool) {
+        match (key, pressed) {
+            (KeyCode::Escape, true) => {
+                event_loop.exit();
+            }
+            (KeyCode::KeyP, true) => {
+                self.pcf = !self.pcf;
+                println!("PCF: {}", self.pcf);
+            }
+            (KeyCode::KeyQ, _pressed) => {
+                self.rotation_speed += 0.1;
+            }
+            (KeyCode::KeyA, _pressed) => {
+                self.rotation_speed -= 0.1;
+                if self.rotation_speed < 0.0 {
+                    self.rotation_speed = 0.0;
+                }
+            }
+            (KeyCode::F12, true) => {
+                self.capture_requested = true;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn update(&mut self, dt: std::time::Duration) {
+        // update uniform buffers
+        let dt = self.rotation_speed * dt.as_secs_f32();
+        self.scene_buffer.write(
+            &self.init.queue,
+            &SceneUniforms {
+                vp_mat: self.project_mat * self.view_mat,
+                light_vp_mat: self.light_vp_mat,
+                light_direction: self.light_direction,
+                pcf: self.pcf as u32,
+            },
+        );
+
+        let queue = &self.init.queue;
+        self.meshes[0].set_model_mat(queue, Mat4::IDENTITY);
+        self.meshes[1].set_model_mat(
+            queue,
+            ws::create_model_mat([-1.0, 1.5, 0.0], [dt.sin(), dt.cos(), 0.0], [1.0, 1.0, 1.0]),
+        );
+        self.meshes[2].set_model_mat(
+            queue,
+            ws::create_model_mat(
+                [1.6, 1.2 + 0.4 * dt.sin(), 1.0],
+                [0.0, 0.0, 0.0],
+                [1.0, 1.0, 1.0],
+            ),
+        );
+    }
+
+    pub fn render(&mut self) -> anyhow::Result<()> {
+        if self.init.is_device_lost() {
+            anyhow::bail!("Lost device");
+        }
+
+        let Some(output) = self.init.acquire_frame()? else {
+            // Skip this frame
+            return Ok(());
+        };
+        let view = output.create_view();
+
+        let mut encoder =
+            self.init
+                .device
+                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                    label: Some("Render Encoder"),
+                });
+
+        // shadow pass: depth seen from the light
+        {
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Shadow Pass"),
+                color_attachments: &[],
+                depth_stencil_attachment: Some(self.shadow_map.depth_stencil_attachment()),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+                multiview_mask: None,
+            });
+
+            render_pass.set_pipeline(&self.shadow_pipeline);
+            render_pass.set_bind_group(0, &self.scene_bind_group, &[]);
+            for mesh in &self.meshes {
+                mesh.draw(&mut render_pass);
+            }
+        }
+
+        // main pass: the scene from the camera, compared against the shadow map
+        {
+            let color_attachment = self.render_target.color_attachment(&view);
+
+            let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+                label: Some("Render Pass"),
+                color_attachments: &[Some(color_attachment)],
+                depth_stencil_attachment: self.render_target.depth_stencil_attachment(),
+                occlusion_query_set: None,
+                timestamp_writes: None,
+                multiview_mask: None,
+            });
+
+            render_pass.set_pipeline(&self.pipeline);
+            render_pass.set_bind_group(0, &self.scene_bind_group, &[]);
+            render_pass.set_bind_group(2, &self.shadow_bind_group, &[]);
+            for mesh in &self.meshes {
+                mesh.draw(&mut render_pass);
+            }
+        }
+
+        self.init.queue.submit(std::iter::once(encoder.finish()));
+
+        // read the frame back before it is presented
+        if std::mem::take(&mut self.capture_requested) {
+            match capture::save_screenshot(&self.init, output.texture()) {
+                Ok(path) => println!("Saved {}", path.display()),
+                Err(e) => log::error!("{e}"),
+            }
+        }
+        output.present();
+
+        Ok(())
+    }
+}
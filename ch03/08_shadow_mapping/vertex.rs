@@ -0,0 +1,39 @@
+use wgpu_fundamentals::vertex_data as vd;
+
+// Ensuring memory alignment
+#[repr(C)]
+#[derive(Copy, Clone, Debug, bytemuck::Pod, bytemuck::Zeroable)]
+pub struct Vertex {
+    position: [f32; 3],
+    normal: [f32; 3],
+}
+
+fn create_vertex_data(pos: &[[f32; 3]], normals: &[[f32; 3]]) -> Vec<Vertex> {
+    pos.iter()
+        .zip(normals)
+        .map(|(&position, &normal)| Vertex { position, normal })
+        .collect()
+}
+
+pub fn create_torus_vertices(r_torus: f32, r_tube: f32, u: u16, v: u16) -> (Vec<Vertex>, Vec<u16>) {
+    let (pos, normals, ind, _) = vd::create_torus_data(r_torus, r_tube, u, v);
+    (create_vertex_data(&pos, &normals), ind)
+}
+
+pub fn create_sphere_vertices(r: f32, u: u16, v: u16) -> (Vec<Vertex>, Vec<u16>) {
+    let (pos, normals, _, ind, _) = vd::create_sphere_data(r, u, v);
+    (create_vertex_data(&pos, &normals), ind)
+}
+
+// a square in the xz plane facing up
+pub fn create_plane_vertices(size: f32) -> (Vec<Vertex>, Vec<u16>) {
+    let s2 = size / 2.0;
+    let pos = [
+        [-s2, 0.0, s2],
+        [s2, 0.0, s2],
+        [s2, 0.0, -s2],
+        [-s2, 0.0, -s2],
+    ];
+    let normals = [[0.0, 1.0, 0.0]; 4];
+    (create_vertex_data(&pos, &normals), vec![0, 1, 2, 2, 3, 0])
+}
@@ -1,4 +1,4 @@
-@binding(0) @group(0) var<uniform> mvpMatrix: mat4x4f;
+#include "mvp.wgsl"
 
 @vertex
 fn vs_main(@location(0) pos: vec4f) -> @builtin(position) vec4f {
@@ -0,0 +1,2 @@
+@binding(0) @group(0) var<uniform> mvpMatrix: mat4x4f;
+
@@ -0,0 +1,11 @@
+// The output of a vertex stage that passes a color per vertex, and the fragment stage that
+// draws the interpolated color.
+struct Output {
+    @builtin(position) Position: vec4f,
+    @location(0) vColor: vec4f,
+};
+
+@fragment
+fn fs_main(@location(0) vColor: vec4f) -> @location(0) vec4f {
+    return vColor;
+}
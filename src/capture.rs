@@ -0,0 +1,326 @@
+use std::fmt;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use crate::wgpu_simplified::InitWgpu;
+
+// region: errors
+#[derive(Debug)]
+pub enum CaptureError {
+    UnsupportedFormat(wgpu::TextureFormat),
+    // The texture (or surface configuration) lacks TextureUsages::COPY_SRC.
+    NotCopySource,
+    Map(wgpu::BufferAsyncError),
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Png {
+        path: PathBuf,
+        source: png::EncodingError,
+    },
+    PngDecode {
+        path: PathBuf,
+        source: png::DecodingError,
+    },
+    // load_png only reads 8-bit RGBA files.
+    UnsupportedPng {
+        path: PathBuf,
+        color_type: png::ColorType,
+        bit_depth: png::BitDepth,
+    },
+    UnsupportedExtension(PathBuf),
+}
+
+impl fmt::Display for CaptureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnsupportedFormat(format) => write!(f, "cannot capture {format:?} textures"),
+            Self::NotCopySource => write!(f, "the texture was not created with COPY_SRC usage"),
+            Self::Map(e) => write!(f, "failed to map the capture buffer: {e}"),
+            Self::Io { path, source } => write!(f, "{}: {source}", path.display()),
+            Self::Png { path, source } => {
+                write!(f, "failed to encode {}: {source}", path.display())
+            }
+            Self::PngDecode { path, source } => {
+                write!(f, "failed to decode {}: {source}", path.display())
+            }
+            Self::UnsupportedPng {
+                path,
+                color_type,
+                bit_depth,
+            } => write!(
+                f,
+                "{}: expected 8-bit RGBA, found {color_type:?} with {bit_depth:?}",
+                path.display()
+            ),
+            Self::UnsupportedExtension(path) => {
+                write!(f, "{}: expected a .png or .ppm file name", path.display())
+            }
+        }
+    }
+}
+
+impl std::error::Error for CaptureError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Map(e) => Some(e),
+            Self::Io { source, .. } => Some(source),
+            Self::Png { source, .. } => Some(source),
+            Self::PngDecode { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+// endregion: errors
+
+// region: capture
+// Tightly packed 8-bit RGBA rows, top row first, with sRGB-encoded color as image files expect.
+#[derive(Clone, Debug)]
+pub struct CapturedImage {
+    pub width: u32,
+    pub height: u32,
+    pub rgba: Vec<u8>,
+}
+
+// Copies mip level 0 of a 2D texture back to the CPU. For the current frame pass the surface
+// texture after submitting the frame's commands and before `present`; offscreen targets work
+// the same way. Waits for the GPU, so it is meant for screenshots, not for every frame.
+pub fn capture_frame(
+    init: &InitWgpu,
+    texture: &wgpu::Texture,
+) -> Result<CapturedImage, CaptureError> {
+    let format = texture.format();
+    let bytes_per_pixel = match format {
+        wgpu::TextureFormat::Rgba8Unorm
+        | wgpu::TextureFormat::Rgba8UnormSrgb
+        | wgpu::TextureFormat::Bgra8Unorm
+        | wgpu::TextureFormat::Bgra8UnormSrgb
+        | wgpu::TextureFormat::Rgb10a2Unorm => 4,
+        wgpu::TextureFormat::Rgba16Float => 8,
+        _ => return Err(CaptureError::UnsupportedFormat(format)),
+    };
+    if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+        return Err(CaptureError::NotCopySource);
+    }
+
+    // Rows of a texture-to-buffer copy must start at multiples of 256 bytes.
+    let (width, height) = (texture.width(), texture.height());
+    let row_bytes = width * bytes_per_pixel;
+    let padded_row_bytes = row_bytes.next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+
+    let staging_buffer = init.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Capture Buffer"),
+        size: padded_row_bytes as u64 * height as u64,
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = init
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Capture Encoder"),
+        });
+    encoder.copy_texture_to_buffer(
+        texture.as_image_copy(),
+        wgpu::TexelCopyBufferInfo {
+            buffer: &staging_buffer,
+            layout: wgpu::TexelCopyBufferLayout {
+                offset: 0,
+                bytes_per_row: Some(padded_row_bytes),
+                rows_per_image: Some(height),
+            },
+        },
+        wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+    );
+    init.queue.submit(std::iter::once(encoder.finish()));
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    staging_buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+    let _ = init.device.poll(wgpu::PollType::wait_indefinitely());
+    receiver
+        .recv()
+        .expect("map_async callback was not called")
+        .map_err(CaptureError::Map)?;
+
+    let mut rgba = Vec::with_capacity((width * height * 4) as usize);
+    {
+        let data = staging_buffer.slice(..).get_mapped_range();
+        for row in data.chunks(padded_row_bytes as usize) {
+            append_rgba(format, &row[..row_bytes as usize], &mut rgba);
+        }
+    }
+    staging_buffer.unmap();
+
+    Ok(CapturedImage {
+        width,
+        height,
+        rgba,
+    })
+}
+
+fn append_rgba(format: wgpu::TextureFormat, row: &[u8], rgba: &mut Vec<u8>) {
+    match format {
+        wgpu::TextureFormat::Bgra8Unorm | wgpu::TextureFormat::Bgra8UnormSrgb => {
+            for pixel in row.chunks_exact(4) {
+                rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+            }
+        }
+        // Float targets hold linear values; encode them like an sRGB surface would.
+        wgpu::TextureFormat::Rgba16Float => {
+            for pixel in row.chunks_exact(8) {
+                let channel = |i: usize| f16_to_f32(u16::from_le_bytes([pixel[i], pixel[i + 1]]));
+                rgba.extend_from_slice(&[
+                    to_unorm8(linear_to_srgb(channel(0))),
+                    to_unorm8(linear_to_srgb(channel(2))),
+                    to_unorm8(linear_to_srgb(channel(4))),
+                    to_unorm8(channel(6)),
+                ]);
+            }
+        }
+        // 10 bits per color channel and 2 for alpha, packed red first into a little-endian u32.
+        wgpu::TextureFormat::Rgb10a2Unorm => {
+            for pixel in row.chunks_exact(4) {
+                let bits = u32::from_le_bytes([pixel[0], pixel[1], pixel[2], pixel[3]]);
+                let channel = |shift: u32| ((bits >> shift) & 0x3ff) as f32 / 1023.0;
+                rgba.extend_from_slice(&[
+                    to_unorm8(channel(0)),
+                    to_unorm8(channel(10)),
+                    to_unorm8(channel(20)),
+                    to_unorm8((bits >> 30) as f32 / 3.0),
+                ]);
+            }
+        }
+        // The bytes of Rgba8UnormSrgb are already sRGB-encoded, and a linear Rgba8Unorm
+        // surface shows its bytes unchanged.
+        _ => rgba.extend_from_slice(row),
+    }
+}
+
+fn f16_to_f32(bits: u16) -> f32 {
+    let sign = if bits & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((bits >> 10) & 0x1f) as i32;
+    let mantissa = (bits & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+fn linear_to_srgb(value: f32) -> f32 {
+    if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    }
+}
+
+fn to_unorm8(value: f32) -> u8 {
+    (value.clamp(0.0, 1.0) * 255.0).round() as u8
+}
+// endregion: capture
+
+// region: files
+impl CapturedImage {
+    // Picks the encoder from the extension, .png or .ppm.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<(), CaptureError> {
+        let path = path.as_ref();
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(e) if e.eq_ignore_ascii_case("png") => self.save_png(path),
+            Some(e) if e.eq_ignore_ascii_case("ppm") => self.save_ppm(path),
+            _ => Err(CaptureError::UnsupportedExtension(path.to_path_buf())),
+        }
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<(), CaptureError> {
+        let path = path.as_ref();
+        let png_error = |source| CaptureError::Png {
+            path: path.to_path_buf(),
+            source,
+        };
+        let file = create_file(path)?;
+        let mut encoder = png::Encoder::new(std::io::BufWriter::new(file), self.width, self.height);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.set_source_srgb(png::SrgbRenderingIntent::Perceptual);
+        let mut writer = encoder.write_header().map_err(png_error)?;
+        writer.write_image_data(&self.rgba).map_err(png_error)?;
+        writer.finish().map_err(png_error)
+    }
+
+    // Reads a PNG written by `save_png`, e.g. a reference image in a test.
+    pub fn load_png(path: impl AsRef<Path>) -> Result<Self, CaptureError> {
+        let path = path.as_ref();
+        let decode_error = |source| CaptureError::PngDecode {
+            path: path.to_path_buf(),
+            source,
+        };
+        let file = std::fs::File::open(path).map_err(|source| CaptureError::Io {
+            path: path.to_path_buf(),
+            source,
+        })?;
+        let mut reader = png::Decoder::new(std::io::BufReader::new(file))
+            .read_info()
+            .map_err(decode_error)?;
+        let (color_type, bit_depth) = reader.output_color_type();
+        if (color_type, bit_depth) != (png::ColorType::Rgba, png::BitDepth::Eight) {
+            return Err(CaptureError::UnsupportedPng {
+                path: path.to_path_buf(),
+                color_type,
+                bit_depth,
+            });
+        }
+        let mut rgba = vec![0; reader.output_buffer_size().unwrap_or(0)];
+        let info = reader.next_frame(&mut rgba).map_err(decode_error)?;
+        rgba.truncate(info.buffer_size());
+        Ok(Self {
+            width: info.width,
+            height: info.height,
+            rgba,
+        })
+    }
+
+    // Binary PPM (P6) has no alpha channel, so alpha is dropped.
+    pub fn save_ppm(&self, path: impl AsRef<Path>) -> Result<(), CaptureError> {
+        let path = path.as_ref();
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        for pixel in self.rgba.chunks_exact(4) {
+            bytes.extend_from_slice(&pixel[..3]);
+        }
+        create_file(path)?
+            .write_all(&bytes)
+            .map_err(|source| CaptureError::Io {
+                path: path.to_path_buf(),
+                source,
+            })
+    }
+}
+
+fn create_file(path: &Path) -> Result<std::fs::File, CaptureError> {
+    std::fs::File::create(path).map_err(|source| CaptureError::Io {
+        path: path.to_path_buf(),
+        source,
+    })
+}
+
+// Captures the texture and saves it as screenshot-<unix time in ms>.png in the working
+// directory. The examples call this for F12.
+pub fn save_screenshot(init: &InitWgpu, texture: &wgpu::Texture) -> Result<PathBuf, CaptureError> {
+    let millis = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map_or(0, |d| d.as_millis());
+    let path = PathBuf::from(format!("screenshot-{millis}.png"));
+    capture_frame(init, texture)?.save(&path)?;
+    Ok(path)
+}
+// endregion: files
@@ -0,0 +1,202 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::reflection::ShaderReflection;
+use crate::shader_composer::{ComposeError, ShaderComposer};
+use crate::wgpu_simplified::{IRenderPipeline, InitWgpu};
+
+// region: errors
+#[derive(Debug)]
+pub enum HotReloadError {
+    Io {
+        path: PathBuf,
+        source: std::io::Error,
+    },
+    Shader {
+        path: PathBuf,
+        source: ComposeError,
+    },
+}
+
+impl fmt::Display for HotReloadError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io { path, source } => write!(f, "failed to read {}: {source}", path.display()),
+            Self::Shader { path, source } => write!(f, "{}: {source}", path.display()),
+        }
+    }
+}
+
+impl std::error::Error for HotReloadError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Io { source, .. } => Some(source),
+            Self::Shader { source, .. } => Some(source),
+        }
+    }
+}
+// endregion: errors
+
+// region: watcher
+// Polls a file's modification time. Checks are throttled to `interval` so polling once per
+// frame doesn't hit the file system every frame.
+pub struct ShaderWatcher {
+    pub path: PathBuf,
+    pub interval: Duration,
+    modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        let path = path.into();
+        Self {
+            modified: modified_time(&path),
+            path,
+            interval: Duration::from_millis(250),
+            last_poll: Instant::now(),
+        }
+    }
+
+    // Returns true once for each change to the file.
+    pub fn poll(&mut self) -> bool {
+        if self.last_poll.elapsed() < self.interval {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = modified_time(&self.path);
+        if modified.is_some() && modified != self.modified {
+            self.modified = modified;
+            return true;
+        }
+        false
+    }
+
+    pub fn read(&self) -> Result<String, HotReloadError> {
+        std::fs::read_to_string(&self.path).map_err(|source| HotReloadError::Io {
+            path: self.path.clone(),
+            source,
+        })
+    }
+}
+
+fn modified_time(path: &std::path::Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+// endregion: watcher
+
+// region: hot shader
+// A WGSL shader loaded from disk that recompiles itself when the file or one of its
+// `include_files` changes. The include files are read from disk under their include names on
+// every compile; files only in `includes` are used as they are and not watched. `reflection`
+// always describes the source `module` was compiled from.
+pub struct HotShader {
+    pub watcher: ShaderWatcher,
+    pub include_files: Vec<(String, ShaderWatcher)>,
+    pub includes: ShaderComposer,
+    pub module: wgpu::ShaderModule,
+    pub reflection: ShaderReflection,
+}
+
+impl HotShader {
+    pub fn load(
+        init: &InitWgpu,
+        path: impl Into<PathBuf>,
+        include_files: &[(&str, &Path)],
+        includes: ShaderComposer,
+    ) -> Result<Self, HotReloadError> {
+        let watcher = ShaderWatcher::new(path);
+        let include_files: Vec<_> = include_files
+            .iter()
+            .map(|(name, path)| (name.to_string(), ShaderWatcher::new(*path)))
+            .collect();
+        let (module, reflection) = compile(init, &watcher, &include_files, &includes)?;
+        Ok(Self {
+            watcher,
+            include_files,
+            includes,
+            module,
+            reflection,
+        })
+    }
+
+    // Returns true when a file changed and the new source compiled; the caller should then
+    // rebuild the pipelines that use `module`. On failure the naga error is logged and the
+    // previous module is kept.
+    pub fn reload_if_changed(&mut self, init: &InitWgpu) -> bool {
+        // poll every watcher so each one takes in its own change
+        let mut changed = self.watcher.poll();
+        for (_, watcher) in self.include_files.iter_mut() {
+            changed |= watcher.poll();
+        }
+        if !changed {
+            return false;
+        }
+        match compile(init, &self.watcher, &self.include_files, &self.includes) {
+            Ok((module, reflection)) => {
+                log::info!("reloaded {}", self.watcher.path.display());
+                self.module = module;
+                self.reflection = reflection;
+                true
+            }
+            Err(e) => {
+                log::error!("{e}");
+                false
+            }
+        }
+    }
+}
+
+// Validates with naga first so a broken shader never reaches wgpu, where it would only
+// surface as an uncaptured error.
+fn compile(
+    init: &InitWgpu,
+    watcher: &ShaderWatcher,
+    include_files: &[(String, ShaderWatcher)],
+    includes: &ShaderComposer,
+) -> Result<(wgpu::ShaderModule, ShaderReflection), HotReloadError> {
+    let source = watcher.read()?;
+    let mut includes = includes.clone();
+    for (name, include_watcher) in include_files {
+        includes.add_file(name, &include_watcher.read()?);
+    }
+    let name = watcher
+        .path
+        .file_name()
+        .map_or_else(String::new, |name| name.to_string_lossy().into_owned());
+    let shader_error = |source| HotReloadError::Shader {
+        path: watcher.path.clone(),
+        source,
+    };
+    let composed = includes
+        .add_file(&name, &source)
+        .compose(&name)
+        .map_err(shader_error)?;
+    let reflection = composed.reflect().map_err(shader_error)?;
+
+    let label = watcher.path.display().to_string();
+    let module = init
+        .device
+        .create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(&label),
+            source: wgpu::ShaderSource::Wgsl(composed.source.into()),
+        });
+    Ok((module, reflection))
+}
+
+// Builds the pipeline inside a validation error scope, so a shader that no longer matches the
+// layouts returns an error instead of leaving an invalid pipeline behind.
+pub fn try_create_pipeline(
+    init: &InitWgpu,
+    ppl: &mut IRenderPipeline,
+) -> Result<wgpu::RenderPipeline, wgpu::Error> {
+    let scope = init.device.push_error_scope(wgpu::ErrorFilter::Validation);
+    let pipeline = ppl.new(init);
+    match pollster::block_on(scope.pop()) {
+        Some(error) => Err(error),
+        None => Ok(pipeline),
+    }
+}
+// endregion: hot shader
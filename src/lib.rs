@@ -1,2 +1,8 @@
+pub mod capture;
+pub mod hot_reload;
+pub mod reflection;
+pub mod shader_composer;
+pub mod shadow;
+pub mod uniform;
 pub mod vertex_data;
 pub mod wgpu_simplified;
@@ -0,0 +1,770 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::num::{NonZeroU32, NonZeroU64};
+
+use naga::valid::{Capabilities, ModuleInfo, ValidationFlags, Validator};
+
+// region: errors
+#[derive(Debug)]
+pub enum ReflectError {
+    // Parse and validation errors are rendered against the source, with line numbers.
+    Parse(String),
+    Validation(String),
+    MissingEntryPoint(String),
+    NotVertexEntryPoint(String),
+    UnsupportedBinding {
+        group: u32,
+        binding: u32,
+        reason: String,
+    },
+    UnsupportedVertexInput {
+        location: u32,
+        ty: String,
+    },
+    MissingAttribute {
+        location: u32,
+        name: Option<String>,
+        ty: String,
+    },
+    AttributeMismatch {
+        location: u32,
+        name: Option<String>,
+        ty: String,
+        format: wgpu::VertexFormat,
+    },
+    DuplicateLocation {
+        location: u32,
+        buffers: (usize, usize),
+    },
+}
+
+impl fmt::Display for ReflectError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Parse(message) => write!(f, "failed to parse WGSL:\n{message}"),
+            Self::Validation(message) => write!(f, "WGSL validation failed:\n{message}"),
+            Self::MissingEntryPoint(name) => write!(f, "the shader has no entry point `{name}`"),
+            Self::NotVertexEntryPoint(name) => {
+                write!(f, "entry point `{name}` is not a vertex shader")
+            }
+            Self::UnsupportedBinding {
+                group,
+                binding,
+                reason,
+            } => write!(
+                f,
+                "@group({group}) @binding({binding}) cannot be reflected: {reason}"
+            ),
+            Self::UnsupportedVertexInput { location, ty } => write!(
+                f,
+                "vertex input @location({location}) has type {ty}, which has no vertex format"
+            ),
+            Self::MissingAttribute { location, name, ty } => write!(
+                f,
+                "the shader reads {} at @location({location}) as {ty}, but no vertex buffer \
+                 layout provides that location",
+                describe_input(name)
+            ),
+            Self::AttributeMismatch {
+                location,
+                name,
+                ty,
+                format,
+            } => write!(
+                f,
+                "the shader reads {} at @location({location}) as {ty}, but the vertex buffer \
+                 layout provides {format:?}",
+                describe_input(name)
+            ),
+            Self::DuplicateLocation { location, buffers } => write!(
+                f,
+                "@location({location}) is provided by both vertex buffer {} and vertex buffer {}",
+                buffers.0, buffers.1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ReflectError {}
+
+fn describe_input(name: &Option<String>) -> String {
+    match name {
+        Some(name) => format!("`{name}`"),
+        None => String::from("an input"),
+    }
+}
+// endregion: errors
+
+// region: reflection
+pub struct ShaderReflection {
+    pub module: naga::Module,
+    pub info: ModuleInfo,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub struct VertexInput {
+    pub location: u32,
+    pub name: Option<String>,
+    pub format: wgpu::VertexFormat,
+}
+
+// A tightly packed single-buffer layout, one attribute per shader input in location order.
+#[derive(Clone, Debug)]
+pub struct VertexLayout {
+    pub array_stride: wgpu::BufferAddress,
+    pub attributes: Vec<wgpu::VertexAttribute>,
+}
+
+impl VertexLayout {
+    pub fn layout(&self, step_mode: wgpu::VertexStepMode) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: self.array_stride,
+            step_mode,
+            attributes: &self.attributes,
+        }
+    }
+}
+
+impl ShaderReflection {
+    pub fn from_wgsl(source: &str) -> Result<Self, ReflectError> {
+        let module = naga::front::wgsl::parse_str(source)
+            .map_err(|e| ReflectError::Parse(e.emit_to_string(source)))?;
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|e| ReflectError::Validation(e.emit_to_string(source)))?;
+        Ok(Self { module, info })
+    }
+
+    fn entry_point(&self, name: &str) -> Result<(usize, &naga::EntryPoint), ReflectError> {
+        self.module
+            .entry_points
+            .iter()
+            .enumerate()
+            .find(|(_, ep)| ep.name == name)
+            .ok_or_else(|| ReflectError::MissingEntryPoint(name.to_string()))
+    }
+
+    // Layout entries for every resource used by `entry_points`, indexed by group and sorted
+    // by binding. Groups the entry points don't use come back empty. Visibility is the union
+    // of the stages that use each binding.
+    pub fn bind_group_layout_entries(
+        &self,
+        entry_points: &[&str],
+    ) -> Result<Vec<Vec<wgpu::BindGroupLayoutEntry>>, ReflectError> {
+        let mut visibility = BTreeMap::new();
+        for name in entry_points {
+            let (index, ep) = self.entry_point(name)?;
+            let function_info = self.info.get_entry_point(index);
+            for (handle, _) in self.module.global_variables.iter() {
+                if !function_info[handle].is_empty() {
+                    *visibility.entry(handle).or_insert(wgpu::ShaderStages::NONE) |=
+                        shader_stage(ep.stage);
+                }
+            }
+        }
+
+        let mut groups: Vec<Vec<wgpu::BindGroupLayoutEntry>> = Vec::new();
+        for (handle, stages) in visibility {
+            let var = &self.module.global_variables[handle];
+            let Some(binding) = &var.binding else {
+                continue;
+            };
+            let (ty, count) = self.binding_type(var, binding)?;
+            let group = binding.group as usize;
+            if groups.len() <= group {
+                groups.resize_with(group + 1, Vec::new);
+            }
+            groups[group].push(wgpu::BindGroupLayoutEntry {
+                binding: binding.binding,
+                visibility: stages,
+                ty,
+                count,
+            });
+        }
+        for entries in groups.iter_mut() {
+            entries.sort_by_key(|entry| entry.binding);
+        }
+        Ok(groups)
+    }
+
+    pub fn create_bind_group_layouts(
+        &self,
+        device: &wgpu::Device,
+        entry_points: &[&str],
+    ) -> Result<Vec<wgpu::BindGroupLayout>, ReflectError> {
+        Ok(self
+            .bind_group_layout_entries(entry_points)?
+            .iter()
+            .enumerate()
+            .map(|(i, entries)| {
+                device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+                    label: Some(&format!("Reflected Bind Group Layout {i}")),
+                    entries,
+                })
+            })
+            .collect())
+    }
+
+    // Convenience for the common case: derive the layouts, then the pipeline layout.
+    pub fn create_pipeline_layout(
+        &self,
+        device: &wgpu::Device,
+        entry_points: &[&str],
+    ) -> Result<(Vec<wgpu::BindGroupLayout>, wgpu::PipelineLayout), ReflectError> {
+        let layouts = self.create_bind_group_layouts(device, entry_points)?;
+        let layout_refs: Vec<_> = layouts.iter().map(Some).collect();
+        let pipeline_layout = device.create_pipeline_layout(&wgpu::PipelineLayoutDescriptor {
+            label: Some("Reflected Pipeline Layout"),
+            bind_group_layouts: &layout_refs,
+            immediate_size: 0,
+        });
+        Ok((layouts, pipeline_layout))
+    }
+
+    // The `@location` inputs of a vertex entry point, including those inside struct arguments.
+    // Each format is the exact match for the WGSL type, e.g. vec3f -> Float32x3.
+    pub fn vertex_inputs(&self, entry_point: &str) -> Result<Vec<VertexInput>, ReflectError> {
+        let (_, ep) = self.entry_point(entry_point)?;
+        if ep.stage != naga::ShaderStage::Vertex {
+            return Err(ReflectError::NotVertexEntryPoint(entry_point.to_string()));
+        }
+
+        let mut inputs = vec![];
+        for arg in ep.function.arguments.iter() {
+            match &self.module.types[arg.ty].inner {
+                naga::TypeInner::Struct { members, .. } if arg.binding.is_none() => {
+                    for member in members {
+                        self.push_vertex_input(
+                            &mut inputs,
+                            &member.binding,
+                            &member.name,
+                            member.ty,
+                        )?;
+                    }
+                }
+                _ => self.push_vertex_input(&mut inputs, &arg.binding, &arg.name, arg.ty)?,
+            }
+        }
+        inputs.sort_by_key(|input| input.location);
+        Ok(inputs)
+    }
+
+    fn push_vertex_input(
+        &self,
+        inputs: &mut Vec<VertexInput>,
+        binding: &Option<naga::Binding>,
+        name: &Option<String>,
+        ty: naga::Handle<naga::Type>,
+    ) -> Result<(), ReflectError> {
+        let Some(naga::Binding::Location { location, .. }) = binding else {
+            return Ok(());
+        };
+        let format = vertex_format(&self.module.types[ty].inner).ok_or_else(|| {
+            ReflectError::UnsupportedVertexInput {
+                location: *location,
+                ty: self.type_name(ty),
+            }
+        })?;
+        inputs.push(VertexInput {
+            location: *location,
+            name: name.clone(),
+            format,
+        });
+        Ok(())
+    }
+
+    pub fn vertex_layout(&self, entry_point: &str) -> Result<VertexLayout, ReflectError> {
+        let mut offset = 0;
+        let attributes = self
+            .vertex_inputs(entry_point)?
+            .into_iter()
+            .map(|input| {
+                let attribute = wgpu::VertexAttribute {
+                    format: input.format,
+                    offset,
+                    shader_location: input.location,
+                };
+                offset += input.format.size();
+                attribute
+            })
+            .collect();
+        Ok(VertexLayout {
+            array_stride: offset,
+            attributes,
+        })
+    }
+
+    // Checks that every shader input is provided exactly once with a compatible format.
+    // As in wgpu, the component counts may differ (a Float32x3 buffer can feed a vec4f input);
+    // only the numeric kind has to match. Attributes the shader doesn't read are allowed.
+    pub fn validate_vertex_layouts(
+        &self,
+        entry_point: &str,
+        layouts: &[wgpu::VertexBufferLayout],
+    ) -> Result<(), ReflectError> {
+        let mut provided = BTreeMap::new();
+        for (i, layout) in layouts.iter().enumerate() {
+            for attribute in layout.attributes {
+                if let Some((j, _)) =
+                    provided.insert(attribute.shader_location, (i, attribute.format))
+                {
+                    return Err(ReflectError::DuplicateLocation {
+                        location: attribute.shader_location,
+                        buffers: (j, i),
+                    });
+                }
+            }
+        }
+
+        for input in self.vertex_inputs(entry_point)? {
+            let ty = wgsl_type_name(input.format);
+            match provided.get(&input.location) {
+                None => {
+                    return Err(ReflectError::MissingAttribute {
+                        location: input.location,
+                        name: input.name,
+                        ty,
+                    });
+                }
+                Some((_, format)) if format_kind(*format) != format_kind(input.format) => {
+                    return Err(ReflectError::AttributeMismatch {
+                        location: input.location,
+                        name: input.name,
+                        ty,
+                        format: *format,
+                    });
+                }
+                Some(_) => {}
+            }
+        }
+        Ok(())
+    }
+
+    fn binding_type(
+        &self,
+        var: &naga::GlobalVariable,
+        binding: &naga::ResourceBinding,
+    ) -> Result<(wgpu::BindingType, Option<NonZeroU32>), ReflectError> {
+        let unsupported = |reason: &str| ReflectError::UnsupportedBinding {
+            group: binding.group,
+            binding: binding.binding,
+            reason: reason.to_string(),
+        };
+
+        let (ty, count) = match self.module.types[var.ty].inner {
+            naga::TypeInner::BindingArray { base, size } => match size {
+                naga::ArraySize::Constant(n) => (base, Some(n)),
+                _ => return Err(unsupported("binding arrays need a constant size")),
+            },
+            _ => (var.ty, None),
+        };
+
+        let binding_type = match (var.space, &self.module.types[ty].inner) {
+            (naga::AddressSpace::Uniform, _) => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(
+                    self.module.types[ty].inner.size(self.module.to_ctx()) as u64,
+                ),
+            },
+            // Storage buffers often end in a runtime-sized array, so no minimum size is set.
+            (naga::AddressSpace::Storage { access }, _) => wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Storage {
+                    read_only: !access.contains(naga::StorageAccess::STORE),
+                },
+                has_dynamic_offset: false,
+                min_binding_size: None,
+            },
+            (_, naga::TypeInner::Sampler { comparison }) => {
+                wgpu::BindingType::Sampler(if *comparison {
+                    wgpu::SamplerBindingType::Comparison
+                } else {
+                    wgpu::SamplerBindingType::Filtering
+                })
+            }
+            (
+                _,
+                naga::TypeInner::Image {
+                    dim,
+                    arrayed,
+                    class,
+                },
+            ) => {
+                let view_dimension = view_dimension(*dim, *arrayed);
+                match class {
+                    naga::ImageClass::Sampled { kind, multi } => wgpu::BindingType::Texture {
+                        sample_type: match kind {
+                            naga::ScalarKind::Sint => wgpu::TextureSampleType::Sint,
+                            naga::ScalarKind::Uint => wgpu::TextureSampleType::Uint,
+                            // Multisampled float textures can't be filtered.
+                            _ => wgpu::TextureSampleType::Float { filterable: !multi },
+                        },
+                        view_dimension,
+                        multisampled: *multi,
+                    },
+                    naga::ImageClass::Depth { multi } => wgpu::BindingType::Texture {
+                        sample_type: wgpu::TextureSampleType::Depth,
+                        view_dimension,
+                        multisampled: *multi,
+                    },
+                    naga::ImageClass::Storage { format, access } => {
+                        wgpu::BindingType::StorageTexture {
+                            access: storage_texture_access(*access),
+                            format: storage_format(*format),
+                            view_dimension,
+                        }
+                    }
+                    naga::ImageClass::External => wgpu::BindingType::ExternalTexture,
+                }
+            }
+            (_, naga::TypeInner::AccelerationStructure { vertex_return }) => {
+                wgpu::BindingType::AccelerationStructure {
+                    vertex_return: *vertex_return,
+                }
+            }
+            _ => return Err(unsupported("unrecognized resource type")),
+        };
+        Ok((binding_type, count))
+    }
+
+    fn type_name(&self, ty: naga::Handle<naga::Type>) -> String {
+        let ty = &self.module.types[ty];
+        match &ty.name {
+            Some(name) => name.clone(),
+            None => format!("{:?}", ty.inner),
+        }
+    }
+}
+// endregion: reflection
+
+// region: conversions
+fn shader_stage(stage: naga::ShaderStage) -> wgpu::ShaderStages {
+    match stage {
+        naga::ShaderStage::Vertex => wgpu::ShaderStages::VERTEX,
+        naga::ShaderStage::Fragment => wgpu::ShaderStages::FRAGMENT,
+        naga::ShaderStage::Compute => wgpu::ShaderStages::COMPUTE,
+        naga::ShaderStage::Task => wgpu::ShaderStages::TASK,
+        naga::ShaderStage::Mesh => wgpu::ShaderStages::MESH,
+        naga::ShaderStage::RayGeneration => wgpu::ShaderStages::RAY_GENERATION,
+        naga::ShaderStage::Miss => wgpu::ShaderStages::MISS,
+        naga::ShaderStage::AnyHit => wgpu::ShaderStages::ANY_HIT,
+        naga::ShaderStage::ClosestHit => wgpu::ShaderStages::CLOSEST_HIT,
+    }
+}
+
+fn view_dimension(dim: naga::ImageDimension, arrayed: bool) -> wgpu::TextureViewDimension {
+    match (dim, arrayed) {
+        (naga::ImageDimension::D1, _) => wgpu::TextureViewDimension::D1,
+        (naga::ImageDimension::D2, false) => wgpu::TextureViewDimension::D2,
+        (naga::ImageDimension::D2, true) => wgpu::TextureViewDimension::D2Array,
+        (naga::ImageDimension::D3, _) => wgpu::TextureViewDimension::D3,
+        (naga::ImageDimension::Cube, false) => wgpu::TextureViewDimension::Cube,
+        (naga::ImageDimension::Cube, true) => wgpu::TextureViewDimension::CubeArray,
+    }
+}
+
+fn storage_texture_access(access: naga::StorageAccess) -> wgpu::StorageTextureAccess {
+    if access.contains(naga::StorageAccess::ATOMIC) {
+        wgpu::StorageTextureAccess::Atomic
+    } else if access.contains(naga::StorageAccess::LOAD | naga::StorageAccess::STORE) {
+        wgpu::StorageTextureAccess::ReadWrite
+    } else if access.contains(naga::StorageAccess::STORE) {
+        wgpu::StorageTextureAccess::WriteOnly
+    } else {
+        wgpu::StorageTextureAccess::ReadOnly
+    }
+}
+
+fn storage_format(format: naga::StorageFormat) -> wgpu::TextureFormat {
+    use naga::StorageFormat as Sf;
+    use wgpu::TextureFormat as Tf;
+    match format {
+        Sf::R8Unorm => Tf::R8Unorm,
+        Sf::R8Snorm => Tf::R8Snorm,
+        Sf::R8Uint => Tf::R8Uint,
+        Sf::R8Sint => Tf::R8Sint,
+        Sf::R16Uint => Tf::R16Uint,
+        Sf::R16Sint => Tf::R16Sint,
+        Sf::R16Float => Tf::R16Float,
+        Sf::Rg8Unorm => Tf::Rg8Unorm,
+        Sf::Rg8Snorm => Tf::Rg8Snorm,
+        Sf::Rg8Uint => Tf::Rg8Uint,
+        Sf::Rg8Sint => Tf::Rg8Sint,
+        Sf::R32Uint => Tf::R32Uint,
+        Sf::R32Sint => Tf::R32Sint,
+        Sf::R32Float => Tf::R32Float,
+        Sf::Rg16Uint => Tf::Rg16Uint,
+        Sf::Rg16Sint => Tf::Rg16Sint,
+        Sf::Rg16Float => Tf::Rg16Float,
+        Sf::Rgba8Unorm => Tf::Rgba8Unorm,
+        Sf::Rgba8Snorm => Tf::Rgba8Snorm,
+        Sf::Rgba8Uint => Tf::Rgba8Uint,
+        Sf::Rgba8Sint => Tf::Rgba8Sint,
+        Sf::Bgra8Unorm => Tf::Bgra8Unorm,
+        Sf::Rgb10a2Uint => Tf::Rgb10a2Uint,
+        Sf::Rgb10a2Unorm => Tf::Rgb10a2Unorm,
+        Sf::Rg11b10Ufloat => Tf::Rg11b10Ufloat,
+        Sf::R64Uint => Tf::R64Uint,
+        Sf::Rg32Uint => Tf::Rg32Uint,
+        Sf::Rg32Sint => Tf::Rg32Sint,
+        Sf::Rg32Float => Tf::Rg32Float,
+        Sf::Rgba16Uint => Tf::Rgba16Uint,
+        Sf::Rgba16Sint => Tf::Rgba16Sint,
+        Sf::Rgba16Float => Tf::Rgba16Float,
+        Sf::Rgba32Uint => Tf::Rgba32Uint,
+        Sf::Rgba32Sint => Tf::Rgba32Sint,
+        Sf::Rgba32Float => Tf::Rgba32Float,
+        Sf::R16Unorm => Tf::R16Unorm,
+        Sf::R16Snorm => Tf::R16Snorm,
+        Sf::Rg16Unorm => Tf::Rg16Unorm,
+        Sf::Rg16Snorm => Tf::Rg16Snorm,
+        Sf::Rgba16Unorm => Tf::Rgba16Unorm,
+        Sf::Rgba16Snorm => Tf::Rgba16Snorm,
+    }
+}
+
+fn vertex_format(inner: &naga::TypeInner) -> Option<wgpu::VertexFormat> {
+    use wgpu::VertexFormat as Vf;
+    let (scalar, size) = match inner {
+        naga::TypeInner::Scalar(scalar) => (*scalar, 1),
+        naga::TypeInner::Vector { size, scalar } => (*scalar, *size as u32),
+        _ => return None,
+    };
+    Some(match (scalar.kind, scalar.width, size) {
+        (naga::ScalarKind::Float, 4, 1) => Vf::Float32,
+        (naga::ScalarKind::Float, 4, 2) => Vf::Float32x2,
+        (naga::ScalarKind::Float, 4, 3) => Vf::Float32x3,
+        (naga::ScalarKind::Float, 4, 4) => Vf::Float32x4,
+        (naga::ScalarKind::Float, 2, 1) => Vf::Float16,
+        (naga::ScalarKind::Float, 2, 2) => Vf::Float16x2,
+        (naga::ScalarKind::Float, 2, 4) => Vf::Float16x4,
+        (naga::ScalarKind::Float, 8, 1) => Vf::Float64,
+        (naga::ScalarKind::Float, 8, 2) => Vf::Float64x2,
+        (naga::ScalarKind::Float, 8, 3) => Vf::Float64x3,
+        (naga::ScalarKind::Float, 8, 4) => Vf::Float64x4,
+        (naga::ScalarKind::Uint, 4, 1) => Vf::Uint32,
+        (naga::ScalarKind::Uint, 4, 2) => Vf::Uint32x2,
+        (naga::ScalarKind::Uint, 4, 3) => Vf::Uint32x3,
+        (naga::ScalarKind::Uint, 4, 4) => Vf::Uint32x4,
+        (naga::ScalarKind::Sint, 4, 1) => Vf::Sint32,
+        (naga::ScalarKind::Sint, 4, 2) => Vf::Sint32x2,
+        (naga::ScalarKind::Sint, 4, 3) => Vf::Sint32x3,
+        (naga::ScalarKind::Sint, 4, 4) => Vf::Sint32x4,
+        _ => return None,
+    })
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum FormatKind {
+    Float,
+    Double,
+    Uint,
+    Sint,
+}
+
+// The shader-side numeric type a vertex format is read as. Normalized formats read as floats.
+fn format_kind(format: wgpu::VertexFormat) -> FormatKind {
+    use wgpu::VertexFormat as Vf;
+    match format {
+        Vf::Uint8 | Vf::Uint8x2 | Vf::Uint8x4 | Vf::Uint16 | Vf::Uint16x2 | Vf::Uint16x4 => {
+            FormatKind::Uint
+        }
+        Vf::Uint32 | Vf::Uint32x2 | Vf::Uint32x3 | Vf::Uint32x4 => FormatKind::Uint,
+        Vf::Sint8 | Vf::Sint8x2 | Vf::Sint8x4 | Vf::Sint16 | Vf::Sint16x2 | Vf::Sint16x4 => {
+            FormatKind::Sint
+        }
+        Vf::Sint32 | Vf::Sint32x2 | Vf::Sint32x3 | Vf::Sint32x4 => FormatKind::Sint,
+        Vf::Float64 | Vf::Float64x2 | Vf::Float64x3 | Vf::Float64x4 => FormatKind::Double,
+        _ => FormatKind::Float,
+    }
+}
+
+fn wgsl_type_name(format: wgpu::VertexFormat) -> String {
+    let scalar = match format_kind(format) {
+        FormatKind::Float if format.size() / format_components(format) == 2 => "f16",
+        FormatKind::Float => "f32",
+        FormatKind::Double => "f64",
+        FormatKind::Uint => "u32",
+        FormatKind::Sint => "i32",
+    };
+    match format_components(format) {
+        1 => scalar.to_string(),
+        n => format!("vec{n}<{scalar}>"),
+    }
+}
+
+fn format_components(format: wgpu::VertexFormat) -> u64 {
+    use wgpu::VertexFormat as Vf;
+    match format {
+        Vf::Float16x2 | Vf::Float32x2 | Vf::Float64x2 | Vf::Uint32x2 | Vf::Sint32x2 => 2,
+        Vf::Float32x3 | Vf::Float64x3 | Vf::Uint32x3 | Vf::Sint32x3 => 3,
+        Vf::Float16x4 | Vf::Float32x4 | Vf::Float64x4 | Vf::Uint32x4 | Vf::Sint32x4 => 4,
+        _ => 1,
+    }
+}
+// endregion: conversions
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SHADER: &str = "
+@group(0) @binding(0) var<uniform> mvp: mat4x4f;
+@group(0) @binding(2) var<uniform> tint: vec4f;
+@group(1) @binding(1) var texture: texture_2d<f32>;
+@group(1) @binding(0) var texture_sampler: sampler;
+@group(2) @binding(0) var<storage, read> unused: array<f32>;
+
+struct Input {
+    @location(0) position: vec3f,
+    @location(1) color: vec4f,
+}
+
+@vertex
+fn vs_main(input: Input, @location(2) instance: u32) -> @builtin(position) vec4f {
+    return mvp * vec4f(input.position, f32(instance)) + tint * input.color;
+}
+
+@fragment
+fn fs_main(@builtin(position) position: vec4f) -> @location(0) vec4f {
+    return textureSample(texture, texture_sampler, position.xy) * tint;
+}
+";
+
+    fn reflect() -> ShaderReflection {
+        ShaderReflection::from_wgsl(SHADER).unwrap()
+    }
+
+    fn buffer_layout(attributes: &[wgpu::VertexAttribute]) -> wgpu::VertexBufferLayout<'_> {
+        wgpu::VertexBufferLayout {
+            array_stride: 0,
+            step_mode: wgpu::VertexStepMode::Vertex,
+            attributes,
+        }
+    }
+
+    #[test]
+    fn bind_group_layout_entries() {
+        let groups = reflect()
+            .bind_group_layout_entries(&["vs_main", "fs_main"])
+            .unwrap();
+        // group 2 isn't used by either entry point
+        assert_eq!(groups.len(), 2);
+
+        let bindings: Vec<_> = groups[0]
+            .iter()
+            .map(|entry| (entry.binding, entry.visibility))
+            .collect();
+        assert_eq!(
+            bindings,
+            [
+                (0, wgpu::ShaderStages::VERTEX),
+                (2, wgpu::ShaderStages::VERTEX_FRAGMENT)
+            ]
+        );
+        assert_eq!(
+            groups[0][0].ty,
+            wgpu::BindingType::Buffer {
+                ty: wgpu::BufferBindingType::Uniform,
+                has_dynamic_offset: false,
+                min_binding_size: NonZeroU64::new(64),
+            }
+        );
+
+        // sorted by binding, not by declaration order
+        assert_eq!(groups[1][0].binding, 0);
+        assert_eq!(
+            groups[1][0].ty,
+            wgpu::BindingType::Sampler(wgpu::SamplerBindingType::Filtering)
+        );
+        assert_eq!(
+            groups[1][1].ty,
+            wgpu::BindingType::Texture {
+                sample_type: wgpu::TextureSampleType::Float { filterable: true },
+                view_dimension: wgpu::TextureViewDimension::D2,
+                multisampled: false,
+            }
+        );
+        assert_eq!(groups[1][1].visibility, wgpu::ShaderStages::FRAGMENT);
+    }
+
+    #[test]
+    fn bind_group_layout_entries_for_one_stage() {
+        let groups = reflect().bind_group_layout_entries(&["vs_main"]).unwrap();
+        assert_eq!(groups.len(), 1);
+        assert_eq!(groups[0].len(), 2);
+        assert!(matches!(
+            reflect().bind_group_layout_entries(&["main"]),
+            Err(ReflectError::MissingEntryPoint(name)) if name == "main"
+        ));
+    }
+
+    #[test]
+    fn vertex_layout_is_tightly_packed() {
+        let layout = reflect().vertex_layout("vs_main").unwrap();
+        assert_eq!(layout.array_stride, 12 + 16 + 4);
+        let attributes: Vec<_> = layout
+            .attributes
+            .iter()
+            .map(|a| (a.shader_location, a.format, a.offset))
+            .collect();
+        assert_eq!(
+            attributes,
+            [
+                (0, wgpu::VertexFormat::Float32x3, 0),
+                (1, wgpu::VertexFormat::Float32x4, 12),
+                (2, wgpu::VertexFormat::Uint32, 28),
+            ]
+        );
+        assert!(matches!(
+            reflect().vertex_layout("fs_main"),
+            Err(ReflectError::NotVertexEntryPoint(_))
+        ));
+    }
+
+    #[test]
+    fn validate_vertex_layouts() {
+        let reflection = reflect();
+        // fewer components than the shader reads are allowed, as in wgpu
+        let vertex = wgpu::vertex_attr_array![0 => Float32x3, 1 => Unorm8x4];
+        let instance = wgpu::vertex_attr_array![2 => Uint32];
+        reflection
+            .validate_vertex_layouts(
+                "vs_main",
+                &[buffer_layout(&vertex), buffer_layout(&instance)],
+            )
+            .unwrap();
+
+        let result = reflection.validate_vertex_layouts("vs_main", &[buffer_layout(&vertex)]);
+        assert!(matches!(
+            result,
+            Err(ReflectError::MissingAttribute { location: 2, name: Some(name), .. })
+                if name == "instance"
+        ));
+
+        let floats = wgpu::vertex_attr_array![2 => Float32];
+        let result = reflection
+            .validate_vertex_layouts("vs_main", &[buffer_layout(&vertex), buffer_layout(&floats)]);
+        assert!(matches!(
+            result,
+            Err(ReflectError::AttributeMismatch {
+                location: 2,
+                format: wgpu::VertexFormat::Float32,
+                ..
+            })
+        ));
+
+        let duplicate = wgpu::vertex_attr_array![1 => Float32x4, 2 => Uint32];
+        let result = reflection.validate_vertex_layouts(
+            "vs_main",
+            &[buffer_layout(&vertex), buffer_layout(&duplicate)],
+        );
+        assert!(matches!(
+            result,
+            Err(ReflectError::DuplicateLocation {
+                location: 1,
+                buffers: (0, 1)
+            })
+        ));
+    }
+}
@@ -0,0 +1,481 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+use naga::valid::{Capabilities, ValidationFlags, Validator};
+
+use crate::reflection::ShaderReflection;
+
+// region: errors
+#[derive(Clone, Debug, PartialEq)]
+pub struct SourceLocation {
+    pub file: String,
+    // 1-based
+    pub line: u32,
+}
+
+impl fmt::Display for SourceLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.file, self.line)
+    }
+}
+
+#[derive(Debug)]
+pub enum ComposeError {
+    MissingFile {
+        name: String,
+        included_from: Option<SourceLocation>,
+    },
+    UnknownDirective {
+        directive: String,
+        location: SourceLocation,
+    },
+    MissingArgument {
+        directive: String,
+        location: SourceLocation,
+    },
+    UnexpectedElse(SourceLocation),
+    UnexpectedEndif(SourceLocation),
+    UnterminatedIf(SourceLocation),
+    // A file that includes itself, directly or through the files it includes.
+    RecursiveInclude {
+        name: String,
+        location: SourceLocation,
+    },
+    // A naga error mapped back to the file and line it came from, with the column on that line.
+    Shader {
+        location: Option<(SourceLocation, u32)>,
+        message: String,
+    },
+}
+
+impl fmt::Display for ComposeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::MissingFile {
+                name,
+                included_from: Some(location),
+            } => write!(f, "{location}: included file \"{name}\" was not added"),
+            Self::MissingFile {
+                name,
+                included_from: None,
+            } => write!(f, "file \"{name}\" was not added"),
+            Self::UnknownDirective {
+                directive,
+                location,
+            } => write!(f, "{location}: unknown directive #{directive}"),
+            Self::MissingArgument {
+                directive,
+                location,
+            } => write!(f, "{location}: #{directive} needs an argument"),
+            Self::UnexpectedElse(location) => write!(f, "{location}: #else without #ifdef"),
+            Self::UnexpectedEndif(location) => write!(f, "{location}: #endif without #ifdef"),
+            Self::UnterminatedIf(location) => write!(f, "{location}: #ifdef is never closed"),
+            Self::RecursiveInclude { name, location } => {
+                write!(f, "{location}: \"{name}\" includes itself")
+            }
+            Self::Shader {
+                location: Some((location, column)),
+                message,
+            } => write!(f, "{location}:{column}: {message}"),
+            Self::Shader {
+                location: None,
+                message,
+            } => write!(f, "{message}"),
+        }
+    }
+}
+
+impl std::error::Error for ComposeError {}
+// endregion: errors
+
+// region: composer
+// Builds WGSL from a virtual file map. Supported directives, each on its own line:
+//   #include "name"   inserts a file added with `add_file`; each file is included only once,
+//                     and a file that includes itself is an error
+//   #define NAME [value]
+//   #ifdef NAME / #ifndef NAME / #else / #endif
+// Defines with a value replace whole identifiers in the code lines that follow them.
+#[derive(Clone, Debug, Default)]
+pub struct ShaderComposer {
+    pub files: HashMap<String, String>,
+    pub defines: HashMap<String, String>,
+}
+
+// The composed source, with the origin of each of its lines.
+#[derive(Clone, Debug)]
+pub struct ComposedShader {
+    pub source: String,
+    pub line_map: Vec<SourceLocation>,
+}
+
+struct Conditional {
+    // Whether the enclosing block is emitted.
+    parent_active: bool,
+    condition: bool,
+    in_else: bool,
+    location: SourceLocation,
+}
+
+impl ShaderComposer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn add_file(&mut self, name: &str, source: &str) -> &mut Self {
+        self.files.insert(name.to_string(), source.to_string());
+        self
+    }
+
+    pub fn define(&mut self, name: &str, value: &str) -> &mut Self {
+        self.defines.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    pub fn compose(&self, entry: &str) -> Result<ComposedShader, ComposeError> {
+        let mut composed = ComposedShader {
+            source: String::new(),
+            line_map: vec![],
+        };
+        let mut defines = self.defines.clone();
+        let mut included = HashSet::new();
+        let mut stack = vec![];
+        self.compose_file(
+            entry,
+            None,
+            &mut defines,
+            &mut included,
+            &mut stack,
+            &mut composed,
+        )?;
+        Ok(composed)
+    }
+
+    fn compose_file(
+        &self,
+        name: &str,
+        included_from: Option<SourceLocation>,
+        defines: &mut HashMap<String, String>,
+        included: &mut HashSet<String>,
+        // the files being composed, outermost first
+        stack: &mut Vec<String>,
+        composed: &mut ComposedShader,
+    ) -> Result<(), ComposeError> {
+        if let Some(location) = included_from.as_ref()
+            && stack.iter().any(|file| file == name)
+        {
+            return Err(ComposeError::RecursiveInclude {
+                name: name.to_string(),
+                location: location.clone(),
+            });
+        }
+        if !included.insert(name.to_string()) {
+            return Ok(());
+        }
+        let source = self
+            .files
+            .get(name)
+            .ok_or_else(|| ComposeError::MissingFile {
+                name: name.to_string(),
+                included_from,
+            })?;
+
+        let mut conditionals: Vec<Conditional> = vec![];
+        for (i, line) in source.lines().enumerate() {
+            let location = SourceLocation {
+                file: name.to_string(),
+                line: i as u32 + 1,
+            };
+            let active = conditionals
+                .last()
+                .is_none_or(|c| c.parent_active && c.condition != c.in_else);
+
+            let Some(directive) = line.trim_start().strip_prefix('#') else {
+                if active {
+                    composed.source.push_str(&substitute(line, defines));
+                    composed.source.push('\n');
+                    composed.line_map.push(location);
+                }
+                continue;
+            };
+
+            let (keyword, argument) = match directive.split_once(char::is_whitespace) {
+                Some((keyword, argument)) => (keyword, argument.trim()),
+                None => (directive.trim(), ""),
+            };
+            let require_argument = || {
+                if argument.is_empty() {
+                    Err(ComposeError::MissingArgument {
+                        directive: keyword.to_string(),
+                        location: location.clone(),
+                    })
+                } else {
+                    Ok(())
+                }
+            };
+
+            match keyword {
+                "ifdef" | "ifndef" => {
+                    require_argument()?;
+                    conditionals.push(Conditional {
+                        parent_active: active,
+                        condition: defines.contains_key(argument) == (keyword == "ifdef"),
+                        in_else: false,
+                        location: location.clone(),
+                    });
+                }
+                "else" => match conditionals.last_mut() {
+                    Some(c) if !c.in_else => c.in_else = true,
+                    _ => return Err(ComposeError::UnexpectedElse(location)),
+                },
+                "endif" => {
+                    if conditionals.pop().is_none() {
+                        return Err(ComposeError::UnexpectedEndif(location));
+                    }
+                }
+                _ if !active => {}
+                "define" => {
+                    require_argument()?;
+                    let (define, value) = match argument.split_once(char::is_whitespace) {
+                        Some((define, value)) => (define, value.trim()),
+                        None => (argument, ""),
+                    };
+                    defines.insert(define.to_string(), value.to_string());
+                }
+                "include" => {
+                    require_argument()?;
+                    let include = argument.trim_matches('"');
+                    stack.push(name.to_string());
+                    let result = self.compose_file(
+                        include,
+                        Some(location),
+                        defines,
+                        included,
+                        stack,
+                        composed,
+                    );
+                    stack.pop();
+                    result?;
+                }
+                _ => {
+                    return Err(ComposeError::UnknownDirective {
+                        directive: keyword.to_string(),
+                        location,
+                    });
+                }
+            }
+        }
+
+        match conditionals.pop() {
+            Some(c) => Err(ComposeError::UnterminatedIf(c.location)),
+            None => Ok(()),
+        }
+    }
+}
+
+// Replaces identifiers that have a non-empty define.
+fn substitute(line: &str, defines: &HashMap<String, String>) -> String {
+    let mut result = String::with_capacity(line.len());
+    let mut rest = line;
+    while let Some(start) = rest.find(|c: char| c.is_ascii_alphabetic() || c == '_') {
+        // Skip digits and suffixes that belong to a number, such as the `u` in `4u`.
+        let prefix = &rest[..start];
+        let in_number = prefix.ends_with(|c: char| c.is_ascii_digit());
+        let len = rest[start..]
+            .find(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .unwrap_or(rest.len() - start);
+        let word = &rest[start..start + len];
+        result.push_str(prefix);
+        match defines.get(word) {
+            Some(value) if !value.is_empty() && !in_number => result.push_str(value),
+            _ => result.push_str(word),
+        }
+        rest = &rest[start + len..];
+    }
+    result.push_str(rest);
+    result
+}
+// endregion: composer
+
+// region: composed shader
+impl ComposedShader {
+    // Where a 1-based line of the composed source came from.
+    pub fn location(&self, line: u32) -> Option<&SourceLocation> {
+        self.line_map.get(line.checked_sub(1)? as usize)
+    }
+
+    // Parses and validates with naga, reporting errors at their original file and line.
+    pub fn reflect(&self) -> Result<ShaderReflection, ComposeError> {
+        let module = naga::front::wgsl::parse_str(&self.source)
+            .map_err(|e| self.shader_error(e.location(&self.source), e.message().to_string()))?;
+        let info = Validator::new(ValidationFlags::all(), Capabilities::all())
+            .validate(&module)
+            .map_err(|e| self.shader_error(e.location(&self.source), error_chain(e.as_inner())))?;
+        Ok(ShaderReflection { module, info })
+    }
+
+    // Validates with naga first, so errors point at the original files instead of surfacing
+    // from wgpu with composed line numbers.
+    pub fn create_shader_module(
+        &self,
+        device: &wgpu::Device,
+        label: &str,
+    ) -> Result<wgpu::ShaderModule, ComposeError> {
+        self.reflect()?;
+        Ok(device.create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: Some(label),
+            source: wgpu::ShaderSource::Wgsl(self.source.as_str().into()),
+        }))
+    }
+
+    fn shader_error(
+        &self,
+        location: Option<naga::SourceLocation>,
+        message: String,
+    ) -> ComposeError {
+        ComposeError::Shader {
+            location: location.and_then(|l| {
+                self.location(l.line_number)
+                    .map(|origin| (origin.clone(), l.line_position))
+            }),
+            message,
+        }
+    }
+}
+
+fn error_chain(error: &dyn std::error::Error) -> String {
+    let mut message = error.to_string();
+    let mut source = error.source();
+    while let Some(e) = source {
+        message.push_str(&format!(": {e}"));
+        source = e.source();
+    }
+    message
+}
+// endregion: composed shader
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn location(file: &str, line: u32) -> SourceLocation {
+        SourceLocation {
+            file: file.to_string(),
+            line,
+        }
+    }
+
+    #[test]
+    fn nested_conditionals() {
+        let source = "\
+#ifdef A
+a
+#ifndef B
+a_not_b
+#else
+a_b
+#endif
+#else
+not_a
+#ifdef B
+not_a_b
+#endif
+#endif
+";
+        let compose = |defines: &[&str]| {
+            let mut composer = ShaderComposer::new();
+            composer.add_file("main.wgsl", source);
+            for define in defines {
+                composer.define(define, "");
+            }
+            composer.compose("main.wgsl").unwrap().source
+        };
+        assert_eq!(compose(&[]), "not_a\n");
+        assert_eq!(compose(&["B"]), "not_a\nnot_a_b\n");
+        assert_eq!(compose(&["A"]), "a\na_not_b\n");
+        assert_eq!(compose(&["A", "B"]), "a\na_b\n");
+    }
+
+    #[test]
+    fn unterminated_ifdef() {
+        let result = ShaderComposer::new()
+            .add_file("main.wgsl", "#ifdef A\n#ifdef B\n#endif\n")
+            .compose("main.wgsl");
+        match result {
+            Err(ComposeError::UnterminatedIf(l)) => assert_eq!(l, location("main.wgsl", 1)),
+            other => panic!("expected UnterminatedIf, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn recursive_include() {
+        let result = ShaderComposer::new()
+            .add_file("a.wgsl", "#include \"b.wgsl\"\n")
+            .add_file("b.wgsl", "\n#include \"a.wgsl\"\n")
+            .compose("a.wgsl");
+        match result {
+            Err(ComposeError::RecursiveInclude { name, location: l }) => {
+                assert_eq!(name, "a.wgsl");
+                assert_eq!(l, location("b.wgsl", 2));
+            }
+            other => panic!("expected RecursiveInclude, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn repeated_include_is_skipped() {
+        let composed = ShaderComposer::new()
+            .add_file("common.wgsl", "common\n")
+            .add_file("a.wgsl", "#include \"common.wgsl\"\n")
+            .add_file(
+                "main.wgsl",
+                "#include \"common.wgsl\"\n#include \"a.wgsl\"\n",
+            )
+            .compose("main.wgsl")
+            .unwrap();
+        assert_eq!(composed.source, "common\n");
+    }
+
+    #[test]
+    fn defines_substitute_whole_identifiers() {
+        let composed = ShaderComposer::new()
+            .define("SCALE", "2.0")
+            .add_file(
+                "main.wgsl",
+                "#define SIZE 4\n#define FLAG\nvar<private> a: array<f32, SIZE>;\n\
+                 const SIZE_X = SIZE * 2u;\nconst b = SCALE * 1e4;\nconst FLAG_ON = FLAG;\n",
+            )
+            .compose("main.wgsl")
+            .unwrap();
+        assert_eq!(
+            composed.source,
+            "var<private> a: array<f32, 4>;\nconst SIZE_X = 4 * 2u;\nconst b = 2.0 * 1e4;\n\
+             const FLAG_ON = FLAG;\n"
+        );
+    }
+
+    #[test]
+    fn line_map_locates_errors_in_included_files() {
+        let composed = ShaderComposer::new()
+            .add_file("math.wgsl", "fn one() -> f32 {\n    return undefined;\n}\n")
+            .add_file(
+                "main.wgsl",
+                "// main\n#include \"math.wgsl\"\n\nfn two() -> f32 {\n    return 2.0 * one();\n}\n",
+            )
+            .compose("main.wgsl")
+            .unwrap();
+        assert_eq!(composed.location(3), Some(&location("math.wgsl", 2)));
+        assert_eq!(composed.location(7), Some(&location("main.wgsl", 5)));
+
+        match composed.reflect() {
+            Err(ComposeError::Shader {
+                location: Some((l, column)),
+                ..
+            }) => {
+                assert_eq!(l, location("math.wgsl", 2));
+                assert_eq!(column, 12);
+            }
+            Err(e) => panic!("expected a located shader error, got {e}"),
+            Ok(_) => panic!("expected a shader error"),
+        }
+    }
+}
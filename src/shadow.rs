@@ -0,0 +1,149 @@
+use glam::{Mat4, Vec3};
+
+use crate::wgpu_simplified::{
+    BindGroupBuilder, IRenderPipeline, InitWgpu, create_depth_stencil_attachment_with,
+    create_shadow_texture_view_with,
+};
+
+// The WGSL side, for ShaderComposer::add_file("shadow.wgsl", SHADOW_WGSL).
+pub const SHADOW_WGSL: &str = include_str!("shadow.wgsl");
+
+// region: light
+// View and projection of a directional light shining along `light_direction` onto a scene
+// that fits in a sphere of radius `half_extent` around `target`. Returns the view, projection
+// and view-projection matrices, like create_vp_mat.
+pub fn create_light_vp_mat(
+    light_direction: Vec3,
+    target: Vec3,
+    half_extent: f32,
+) -> (Mat4, Mat4, Mat4) {
+    let direction = light_direction.normalize();
+    let up_direction = if direction.cross(Vec3::Y).length_squared() < 1e-6 {
+        Vec3::Z
+    } else {
+        Vec3::Y
+    };
+    let light_position = target - direction * 2.0 * half_extent;
+    let view_mat = Mat4::look_at_rh(light_position, target, up_direction);
+
+    // glam's orthographic_rh already maps depth to 0..1, so OPENGL_TO_WGPU_MATRIX isn't applied
+    let project_mat = Mat4::orthographic_rh(
+        -half_extent,
+        half_extent,
+        -half_extent,
+        half_extent,
+        half_extent,
+        3.0 * half_extent,
+    );
+
+    (view_mat, project_mat, project_mat * view_mat)
+}
+// endregion: light
+
+// region: shadow map
+pub struct IShadowMap {
+    pub size: u32,
+    // Independent of the depth format of InitWgpu, which may include stencil and then can't
+    // be sampled as a whole. Depth16Unorm halves the memory at the cost of precision.
+    pub format: wgpu::TextureFormat,
+    // Linear also blends each comparison over 2x2 texels, on top of the PCF kernel.
+    pub filter: wgpu::FilterMode,
+}
+
+impl Default for IShadowMap {
+    fn default() -> Self {
+        Self {
+            size: 2048,
+            format: wgpu::TextureFormat::Depth32Float,
+            filter: wgpu::FilterMode::Linear,
+        }
+    }
+}
+
+impl IShadowMap {
+    pub fn build(&self, init: &InitWgpu) -> ShadowMap {
+        ShadowMap {
+            view: create_shadow_texture_view_with(init, self.size, self.size, self.format),
+            sampler: create_comparison_sampler(init, self.filter),
+        }
+    }
+}
+
+// The depth texture of a shadow pass and the sampler that compares against it:
+//
+// encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+//     color_attachments: &[],
+//     depth_stencil_attachment: Some(shadow_map.depth_stencil_attachment()),
+//     ..
+// });
+pub struct ShadowMap {
+    pub view: wgpu::TextureView,
+    pub sampler: wgpu::Sampler,
+}
+
+impl ShadowMap {
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.view.texture().format()
+    }
+
+    pub fn size(&self) -> u32 {
+        self.view.texture().width()
+    }
+
+    // Cleared to the far plane and stored for the passes that sample it.
+    pub fn depth_stencil_attachment(&self) -> wgpu::RenderPassDepthStencilAttachment<'_> {
+        create_depth_stencil_attachment_with(&self.view, wgpu::CompareFunction::LessEqual, true)
+    }
+
+    // Settings for the depth-only pipeline of the shadow pass. Add the vertex shader, layouts
+    // and entry point with struct update syntax, then call `new`. The depth bias keeps lit
+    // surfaces from shadowing themselves (shadow acne).
+    pub fn pipeline(&self) -> IRenderPipeline<'static> {
+        IRenderPipeline {
+            label: "Shadow Pipeline",
+            depth_format: Some(self.format()),
+            sample_count: Some(1),
+            depth_bias: wgpu::DepthBiasState {
+                constant: 2,
+                slope_scale: 2.0,
+                clamp: 0.0,
+            },
+            ..Default::default()
+        }
+    }
+
+    // The map at binding 0 and the sampler at binding 1, as shadow.wgsl declares them. More
+    // entries can be added before `build`.
+    pub fn bind_group_builder(&self) -> BindGroupBuilder<'_> {
+        BindGroupBuilder::new("Shadow Bind Group")
+            .texture(
+                0,
+                wgpu::ShaderStages::FRAGMENT,
+                &self.view,
+                wgpu::TextureSampleType::Depth,
+                wgpu::TextureViewDimension::D2,
+            )
+            .sampler(
+                1,
+                wgpu::ShaderStages::FRAGMENT,
+                &self.sampler,
+                wgpu::SamplerBindingType::Comparison,
+            )
+    }
+}
+
+// Returns 1.0 where the compared depth is not farther than the stored one. Lookups outside
+// the map clamp to its edge.
+pub fn create_comparison_sampler(init: &InitWgpu, filter: wgpu::FilterMode) -> wgpu::Sampler {
+    init.device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Shadow Sampler"),
+        address_mode_u: wgpu::AddressMode::ClampToEdge,
+        address_mode_v: wgpu::AddressMode::ClampToEdge,
+        address_mode_w: wgpu::AddressMode::ClampToEdge,
+        mag_filter: filter,
+        min_filter: filter,
+        compare: Some(wgpu::CompareFunction::LessEqual),
+        ..Default::default()
+    })
+}
+// endregion: shadow map
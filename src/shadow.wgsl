@@ -0,0 +1,47 @@
+// Shadow-map lookups for fragment shaders, added to a ShaderComposer as "shadow.wgsl".
+// SHADOW_GROUP is the bind group holding the map at binding 0 and the comparison sampler at
+// binding 1, as made by ShadowMap::bind_group_builder. SHADOW_PCF_RADIUS sets the filter
+// size of shadow_pcf; 1 is a 3x3 kernel.
+#ifndef SHADOW_GROUP
+#define SHADOW_GROUP 1
+#endif
+#ifndef SHADOW_PCF_RADIUS
+#define SHADOW_PCF_RADIUS 1
+#endif
+
+@group(SHADOW_GROUP) @binding(0) var shadow_map: texture_depth_2d;
+@group(SHADOW_GROUP) @binding(1) var shadow_sampler: sampler_comparison;
+
+// Shadow-map uv and depth of a position multiplied by the light's view-projection matrix.
+fn shadow_coords(light_pos: vec4f) -> vec3f {
+    let ndc = light_pos.xyz / light_pos.w;
+    return vec3f(ndc.xy * vec2f(0.5, -0.5) + 0.5, ndc.z);
+}
+
+// Positions the light's frustum doesn't cover are treated as lit.
+fn outside_shadow_map(coords: vec3f) -> bool {
+    return any(coords.xy < vec2f(0.0)) || any(coords.xy > vec2f(1.0)) || coords.z > 1.0;
+}
+
+// 1.0 where lit and 0.0 in shadow, from a single comparison.
+fn shadow_hard(light_pos: vec4f) -> f32 {
+    let coords = shadow_coords(light_pos);
+    let lit = textureSampleCompareLevel(shadow_map, shadow_sampler, coords.xy, coords.z);
+    return select(lit, 1.0, outside_shadow_map(coords));
+}
+
+// Percentage-closer filtering: the average of comparisons one texel apart, which softens the
+// shadow edges.
+fn shadow_pcf(light_pos: vec4f) -> f32 {
+    let coords = shadow_coords(light_pos);
+    let texel = 1.0 / vec2f(textureDimensions(shadow_map));
+    var lit = 0.0;
+    for (var y = -SHADOW_PCF_RADIUS; y <= SHADOW_PCF_RADIUS; y++) {
+        for (var x = -SHADOW_PCF_RADIUS; x <= SHADOW_PCF_RADIUS; x++) {
+            let uv = coords.xy + vec2f(f32(x), f32(y)) * texel;
+            lit += textureSampleCompareLevel(shadow_map, shadow_sampler, uv, coords.z);
+        }
+    }
+    let size = f32(2 * SHADOW_PCF_RADIUS + 1);
+    return select(lit / (size * size), 1.0, outside_shadow_map(coords));
+}
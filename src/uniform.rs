@@ -0,0 +1,325 @@
+use std::marker::PhantomData;
+
+use glam::{IVec2, IVec3, IVec4, Mat2, Mat3, Mat4, UVec2, UVec3, UVec4, Vec2, Vec3, Vec4};
+use wgpu::util::DeviceExt;
+
+// region: layout
+// Uniform buffers add two rules on top of the storage layout: struct and array alignments are
+// rounded up to 16 and array strides are multiples of 16.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AddressSpace {
+    Uniform,
+    Storage,
+}
+
+// A host type with a WGSL counterpart. `align` and `size` follow the WGSL memory layout rules,
+// and `write` fills `out[..size]` with the value laid out the way the shader reads it.
+pub trait WgslType {
+    fn align(space: AddressSpace) -> u64;
+    fn size(space: AddressSpace) -> u64;
+    fn write(&self, space: AddressSpace, out: &mut [u8]);
+}
+
+pub const fn round_up(align: u64, n: u64) -> u64 {
+    n.div_ceil(align) * align
+}
+
+// Alignment of a struct whose largest member alignment is `max_member_align`.
+pub fn struct_align(space: AddressSpace, max_member_align: u64) -> u64 {
+    match space {
+        AddressSpace::Uniform => round_up(16, max_member_align),
+        AddressSpace::Storage => max_member_align,
+    }
+}
+
+pub fn to_bytes<T: WgslType>(value: &T, space: AddressSpace) -> Vec<u8> {
+    let mut bytes = vec![0; T::size(space) as usize];
+    value.write(space, &mut bytes);
+    bytes
+}
+
+macro_rules! impl_wgsl_pod {
+    ($($ty:ty => $align:expr, $size:expr, $to_pod:path;)*) => {
+        $(
+            impl WgslType for $ty {
+                fn align(_space: AddressSpace) -> u64 {
+                    $align
+                }
+                fn size(_space: AddressSpace) -> u64 {
+                    $size
+                }
+                fn write(&self, _space: AddressSpace, out: &mut [u8]) {
+                    out[..$size].copy_from_slice(bytemuck::bytes_of(&$to_pod(self)));
+                }
+            }
+        )*
+    };
+}
+
+impl_wgsl_pod! {
+    f32 => 4, 4, f32::clone;
+    i32 => 4, 4, i32::clone;
+    u32 => 4, 4, u32::clone;
+    Vec2 => 8, 8, Vec2::to_array;
+    IVec2 => 8, 8, IVec2::to_array;
+    UVec2 => 8, 8, UVec2::to_array;
+    Vec3 => 16, 12, Vec3::to_array;
+    IVec3 => 16, 12, IVec3::to_array;
+    UVec3 => 16, 12, UVec3::to_array;
+    Vec4 => 16, 16, Vec4::to_array;
+    IVec4 => 16, 16, IVec4::to_array;
+    UVec4 => 16, 16, UVec4::to_array;
+    Mat2 => 8, 16, Mat2::to_cols_array;
+    Mat4 => 16, 64, Mat4::to_cols_array;
+}
+
+// mat3x3f stores each column as a vec3f padded to 16 bytes.
+impl WgslType for Mat3 {
+    fn align(_space: AddressSpace) -> u64 {
+        16
+    }
+    fn size(_space: AddressSpace) -> u64 {
+        48
+    }
+    fn write(&self, space: AddressSpace, out: &mut [u8]) {
+        for (i, column) in [self.x_axis, self.y_axis, self.z_axis].iter().enumerate() {
+            column.write(space, &mut out[i * 16..]);
+        }
+    }
+}
+
+// array<T, N>; the elements are padded to the array stride.
+impl<T: WgslType, const N: usize> WgslType for [T; N] {
+    fn align(space: AddressSpace) -> u64 {
+        struct_align(space, T::align(space))
+    }
+    fn size(space: AddressSpace) -> u64 {
+        N as u64 * array_stride::<T>(space)
+    }
+    fn write(&self, space: AddressSpace, out: &mut [u8]) {
+        let stride = array_stride::<T>(space) as usize;
+        for (i, element) in self.iter().enumerate() {
+            element.write(space, &mut out[i * stride..]);
+        }
+    }
+}
+
+pub fn array_stride<T: WgslType>(space: AddressSpace) -> u64 {
+    let stride = round_up(T::align(space), T::size(space));
+    match space {
+        AddressSpace::Uniform => round_up(16, stride),
+        AddressSpace::Storage => stride,
+    }
+}
+
+// Defines a struct and implements WgslType for it with the WGSL struct layout, so vec3 members
+// get their padding without it appearing in the Rust type:
+//
+// wgsl_struct! {
+//     pub struct Light {
+//         pub position: Vec3,
+//         pub intensity: f32,
+//         pub color: Vec3,
+//     }
+// }
+#[macro_export]
+macro_rules! wgsl_struct {
+    (
+        $(#[$attr:meta])*
+        $vis:vis struct $name:ident {
+            $($(#[$field_attr:meta])* $field_vis:vis $field:ident : $ty:ty),* $(,)?
+        }
+    ) => {
+        $(#[$attr])*
+        $vis struct $name {
+            $($(#[$field_attr])* $field_vis $field: $ty),*
+        }
+
+        impl $crate::uniform::WgslType for $name {
+            fn align(space: $crate::uniform::AddressSpace) -> u64 {
+                let mut align = 1;
+                $(align = align.max(<$ty as $crate::uniform::WgslType>::align(space));)*
+                $crate::uniform::struct_align(space, align)
+            }
+            fn size(space: $crate::uniform::AddressSpace) -> u64 {
+                let mut offset = 0;
+                $(
+                    offset = $crate::uniform::round_up(
+                        <$ty as $crate::uniform::WgslType>::align(space),
+                        offset,
+                    ) + <$ty as $crate::uniform::WgslType>::size(space);
+                )*
+                $crate::uniform::round_up(Self::align(space), offset)
+            }
+            fn write(&self, space: $crate::uniform::AddressSpace, out: &mut [u8]) {
+                let mut offset = 0;
+                $(
+                    offset = $crate::uniform::round_up(
+                        <$ty as $crate::uniform::WgslType>::align(space),
+                        offset,
+                    );
+                    $crate::uniform::WgslType::write(&self.$field, space, &mut out[offset as usize..]);
+                    offset += <$ty as $crate::uniform::WgslType>::size(space);
+                )*
+                let _ = offset;
+            }
+        }
+    };
+}
+// endregion: layout
+
+// region: buffers
+// A buffer holding one T laid out for `var<uniform>`.
+pub struct UniformBuffer<T: WgslType> {
+    pub buffer: wgpu::Buffer,
+    _marker: PhantomData<T>,
+}
+
+impl<T: WgslType> UniformBuffer<T> {
+    pub fn new(device: &wgpu::Device, value: &T) -> Self {
+        Self {
+            buffer: create_buffer(
+                device,
+                "Uniform Buffer",
+                &to_bytes(value, AddressSpace::Uniform),
+                wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+            ),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, &to_bytes(value, AddressSpace::Uniform));
+    }
+
+    pub fn binding(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
+
+// A buffer holding one T laid out for `var<storage>`.
+pub struct StorageBuffer<T: WgslType> {
+    pub buffer: wgpu::Buffer,
+    _marker: PhantomData<T>,
+}
+
+impl<T: WgslType> StorageBuffer<T> {
+    pub fn new(device: &wgpu::Device, value: &T) -> Self {
+        Self {
+            buffer: create_buffer(
+                device,
+                "Storage Buffer",
+                &to_bytes(value, AddressSpace::Storage),
+                wgpu::BufferUsages::STORAGE
+                    | wgpu::BufferUsages::COPY_DST
+                    | wgpu::BufferUsages::COPY_SRC,
+            ),
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn write(&self, queue: &wgpu::Queue, value: &T) {
+        queue.write_buffer(&self.buffer, 0, &to_bytes(value, AddressSpace::Storage));
+    }
+
+    pub fn binding(&self) -> wgpu::BindingResource<'_> {
+        self.buffer.as_entire_binding()
+    }
+}
+
+// Many T values in one uniform buffer, each at a multiple of
+// `min_uniform_buffer_offset_alignment`, for binding with dynamic offsets:
+//
+// arena.clear();
+// let offsets: Vec<u32> = objects.iter().map(|o| arena.push(&o.uniforms)).collect();
+// if arena.upload(&device, &queue) { /* the buffer was replaced; rebuild the bind group */ }
+// render_pass.set_bind_group(0, &bind_group, &[offsets[i]]);
+pub struct UniformArena<T: WgslType> {
+    pub buffer: wgpu::Buffer,
+    pub stride: u64,
+    data: Vec<u8>,
+    _marker: PhantomData<T>,
+}
+
+impl<T: WgslType> UniformArena<T> {
+    pub fn new(device: &wgpu::Device, capacity: u64) -> Self {
+        let alignment = device.limits().min_uniform_buffer_offset_alignment as u64;
+        let stride = round_up(alignment, T::size(AddressSpace::Uniform));
+        Self {
+            buffer: create_arena_buffer(device, stride * capacity.max(1)),
+            stride,
+            data: vec![],
+            _marker: PhantomData,
+        }
+    }
+
+    pub fn len(&self) -> u64 {
+        self.data.len() as u64 / self.stride
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn capacity(&self) -> u64 {
+        self.buffer.size() / self.stride
+    }
+
+    pub fn clear(&mut self) {
+        self.data.clear();
+    }
+
+    // Appends a value and returns its dynamic offset.
+    pub fn push(&mut self, value: &T) -> u32 {
+        let offset = self.data.len();
+        self.data.resize(offset + self.stride as usize, 0);
+        value.write(AddressSpace::Uniform, &mut self.data[offset..]);
+        offset as u32
+    }
+
+    // Copies the pushed values to the GPU. Returns true when the buffer had to grow, in which
+    // case bind groups made from the old buffer must be recreated.
+    pub fn upload(&mut self, device: &wgpu::Device, queue: &wgpu::Queue) -> bool {
+        let needed = self.data.len() as u64;
+        let grown = needed > self.buffer.size();
+        if grown {
+            self.buffer = create_arena_buffer(device, needed.next_power_of_two());
+        }
+        if !self.data.is_empty() {
+            queue.write_buffer(&self.buffer, 0, &self.data);
+        }
+        grown
+    }
+
+    // One element of the buffer, as bound with a dynamic offset.
+    pub fn binding(&self) -> wgpu::BufferBinding<'_> {
+        wgpu::BufferBinding {
+            buffer: &self.buffer,
+            offset: 0,
+            size: std::num::NonZeroU64::new(T::size(AddressSpace::Uniform)),
+        }
+    }
+}
+
+fn create_arena_buffer(device: &wgpu::Device, size: u64) -> wgpu::Buffer {
+    device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Uniform Arena"),
+        size,
+        usage: wgpu::BufferUsages::UNIFORM | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    })
+}
+
+fn create_buffer(
+    device: &wgpu::Device,
+    label: &str,
+    contents: &[u8],
+    usage: wgpu::BufferUsages,
+) -> wgpu::Buffer {
+    device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
+        label: Some(label),
+        contents,
+        usage,
+    })
+}
+// endregion: buffers
@@ -1,26 +1,107 @@
 use glam::{Mat4, Vec3};
-use std::collections::VecDeque; // HashMap
+use std::collections::{HashMap, VecDeque};
 use std::f32::consts::PI;
+use std::fmt;
+use std::path::{Path, PathBuf};
 use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::{Duration, Instant};
 use winit::window::Window;
 
 // region: wgpu initialization
 pub struct InitWgpu {
-    pub surface: wgpu::Surface<'static>,
+    pub instance: wgpu::Instance,
+    pub surface: Option<wgpu::Surface<'static>>,
     pub adapter: wgpu::Adapter,
     pub device: wgpu::Device,
     pub queue: wgpu::Queue,
     pub config: wgpu::SurfaceConfiguration,
     pub size: winit::dpi::PhysicalSize<u32>,
     pub sample_count: u32,
-    pub window: Arc<Window>,
+    pub depth_format: wgpu::TextureFormat,
+    pub window: Option<Arc<Window>>,
+    // Color target used in place of the surface texture when running headless.
+    pub offscreen_texture: Option<wgpu::Texture>,
+    pub options: InitWgpuOptions,
+    // Set by the device-lost callback and by fatal uncaptured errors.
+    pub device_lost: Arc<AtomicBool>,
+}
+
+#[derive(Clone, Debug)]
+pub struct InitWgpuOptions {
+    pub backends: wgpu::Backends,
+    pub power_preference: wgpu::PowerPreference,
+    pub force_fallback_adapter: bool,
+    // When left at `AdapterSelector::Default`, the WGPU_ADAPTER environment variable is used.
+    pub adapter: AdapterSelector,
+    pub required_features: wgpu::Features,
+    pub required_limits: wgpu::Limits,
+    pub surface_format: SurfaceFormatPreference,
+    // Depth24PlusStencil8 and Depth32FloatStencil8 add a stencil aspect; the feature needed by
+    // Depth32FloatStencil8 is requested automatically.
+    pub depth_format: wgpu::TextureFormat,
+    pub present_mode: wgpu::PresentMode,
+    pub desired_maximum_frame_latency: u32,
+    // Use the largest supported sample count below an unsupported request instead of failing.
+    pub clamp_sample_count: bool,
+}
+
+impl Default for InitWgpuOptions {
+    fn default() -> Self {
+        Self {
+            backends: wgpu::Backends::PRIMARY,
+            power_preference: wgpu::PowerPreference::default(),
+            force_fallback_adapter: false,
+            adapter: AdapterSelector::Default,
+            required_features: wgpu::Features::empty(),
+            required_limits: wgpu::Limits::default(),
+            surface_format: SurfaceFormatPreference::Srgb,
+            depth_format: wgpu::TextureFormat::Depth24Plus,
+            present_mode: wgpu::PresentMode::Fifo,
+            desired_maximum_frame_latency: 2,
+            clamp_sample_count: false,
+        }
+    }
+}
+
+impl InitWgpuOptions {
+    // Reads WGPU_ADAPTER when `adapter` is left at Default, and adds the backends a
+    // `AdapterSelector::Backend` asks for to `backends`, so "backend:gl" finds GL adapters even
+    // though GL is not one of the PRIMARY backends.
+    fn resolve_adapter(&self) -> Self {
+        let mut options = self.clone();
+        if options.adapter == AdapterSelector::Default {
+            options.adapter = AdapterSelector::from_env().unwrap_or_default();
+        }
+        if let AdapterSelector::Backend(backends) = options.adapter {
+            options.backends |= backends;
+        }
+        options
+    }
 }
 
 impl InitWgpu {
     pub async fn init_wgpu(window: Arc<Window>, sample_count: u32) -> Self {
+        Self::try_init_wgpu(window, sample_count)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub async fn try_init_wgpu(
+        window: Arc<Window>,
+        sample_count: u32,
+    ) -> Result<Self, InitWgpuError> {
+        Self::try_init_wgpu_with_options(window, sample_count, &InitWgpuOptions::default()).await
+    }
+
+    pub async fn try_init_wgpu_with_options(
+        window: Arc<Window>,
+        sample_count: u32,
+        options: &InitWgpuOptions,
+    ) -> Result<Self, InitWgpuError> {
+        let options = &options.resolve_adapter();
         let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
-            backends: wgpu::Backends::PRIMARY,
+            backends: options.backends,
             flags: Default::default(),
             memory_budget_thresholds: Default::default(),
             backend_options: Default::default(),
@@ -28,74 +109,889 @@ impl InitWgpu {
         });
 
         // Surface
-        let surface = instance.create_surface(window.clone()).unwrap();
+        let surface = instance
+            .create_surface(window.clone())
+            .map_err(InitWgpuError::CreateSurface)?;
 
-        // Adapter:
-        let adapter = instance
-            .request_adapter(&wgpu::RequestAdapterOptions {
-                power_preference: wgpu::PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-                ..Default::default()
-            })
-            .await
-            .unwrap();
-
-        // Logical Device and Queue
-        let (device, queue) = adapter
-            .request_device(&wgpu::DeviceDescriptor {
-                label: None,
-                required_features: wgpu::Features::empty(),
-                required_limits: wgpu::Limits::default(),
-                ..Default::default()
-            })
-            .await
-            .unwrap();
+        // Adapter, logical device and queue
+        let (adapter, device, queue) = request_device(&instance, options, Some(&surface)).await?;
 
         let size = window.inner_size();
 
+        // An adapter that cannot present to the surface reports empty capabilities.
         let surface_caps = surface.get_capabilities(&adapter);
-        let format = surface_caps.formats[0];
+        if surface_caps.formats.is_empty() || surface_caps.alpha_modes.is_empty() {
+            return Err(InitWgpuError::SurfaceUnsupported {
+                adapter: adapter.get_info(),
+            });
+        }
+        let format = match options.surface_format.select(&surface_caps.formats) {
+            Some(format) => format,
+            None => match &options.surface_format {
+                SurfaceFormatPreference::Explicit(formats) => {
+                    return Err(InitWgpuError::NoCompatibleFormat {
+                        adapter: adapter.get_info(),
+                        formats: formats.clone(),
+                    });
+                }
+                preference => {
+                    log::warn!(
+                        "no surface format matches {preference:?}, using {:?}",
+                        surface_caps.formats[0]
+                    );
+                    surface_caps.formats[0]
+                }
+            },
+        };
+        log::info!("surface format: {format:?}");
 
-        // Defines how a Surface creates a SurfaceTexture.
+        let sample_count =
+            check_sample_count(&adapter, format, sample_count, options).map_err(|supported| {
+                InitWgpuError::UnsupportedSampleCount {
+                    adapter: adapter.get_info(),
+                    requested: sample_count,
+                    supported,
+                }
+            })?;
+
+        let present_mode = resolve_present_mode(options.present_mode, &surface_caps.present_modes);
+
+        // Defines how a Surface creates a SurfaceTexture. COPY_SRC, where the surface allows it,
+        // lets `capture::capture_frame` read the frame back.
         let config = wgpu::SurfaceConfiguration {
-            usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                | (surface_caps.usages & wgpu::TextureUsages::COPY_SRC),
             format,
             width: size.width,
             height: size.height,
-            present_mode: wgpu::PresentMode::Fifo,
+            present_mode,
             alpha_mode: surface_caps.alpha_modes[0],
-            desired_maximum_frame_latency: 2,
+            desired_maximum_frame_latency: options.desired_maximum_frame_latency,
             view_formats: vec![],
         };
 
         surface.configure(&device, &config);
+        let device_lost = watch_device(&device);
 
-        Self {
-            surface,
+        Ok(Self {
+            instance,
+            surface: Some(surface),
             adapter,
             device,
             queue,
             config,
             size,
             sample_count,
-            window: window,
+            depth_format: options.depth_format,
+            window: Some(window),
+            offscreen_texture: None,
+            options: options.clone(),
+            device_lost,
+        })
+    }
+
+    pub async fn init_headless(
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Self {
+        Self::try_init_headless(width, height, format, sample_count)
+            .await
+            .unwrap_or_else(|e| panic!("{e}"))
+    }
+
+    pub async fn try_init_headless(
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+    ) -> Result<Self, InitWgpuError> {
+        // Without a display, software adapters (e.g. GL on llvmpipe) are often the only option.
+        let options = InitWgpuOptions {
+            backends: wgpu::Backends::all(),
+            ..Default::default()
+        };
+        Self::try_init_headless_with_options(width, height, format, sample_count, &options).await
+    }
+
+    pub async fn try_init_headless_with_options(
+        width: u32,
+        height: u32,
+        format: wgpu::TextureFormat,
+        sample_count: u32,
+        options: &InitWgpuOptions,
+    ) -> Result<Self, InitWgpuError> {
+        let options = &options.resolve_adapter();
+        let instance = wgpu::Instance::new(wgpu::InstanceDescriptor {
+            backends: options.backends,
+            flags: Default::default(),
+            memory_budget_thresholds: Default::default(),
+            backend_options: Default::default(),
+            display: None,
+        });
+
+        // Adapter, logical device and queue
+        let (adapter, device, queue) = request_device(&instance, options, None).await?;
+
+        let format_features = adapter.get_texture_format_features(format);
+        if !format_features.allowed_usages.contains(OFFSCREEN_USAGES) {
+            return Err(InitWgpuError::NoCompatibleFormat {
+                adapter: adapter.get_info(),
+                formats: vec![format],
+            });
         }
+
+        let sample_count =
+            check_sample_count(&adapter, format, sample_count, options).map_err(|supported| {
+                InitWgpuError::UnsupportedSampleCount {
+                    adapter: adapter.get_info(),
+                    requested: sample_count,
+                    supported,
+                }
+            })?;
+
+        // There is no surface to configure, but the helpers in this module read the target
+        // size and format from the configuration, so it describes the offscreen texture.
+        let config = wgpu::SurfaceConfiguration {
+            usage: OFFSCREEN_USAGES,
+            format,
+            width,
+            height,
+            present_mode: wgpu::PresentMode::Fifo,
+            alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+            desired_maximum_frame_latency: options.desired_maximum_frame_latency,
+            view_formats: vec![],
+        };
+
+        let offscreen_texture = create_offscreen_texture(&device, &config);
+        let device_lost = watch_device(&device);
+
+        Ok(Self {
+            instance,
+            surface: None,
+            adapter,
+            device,
+            queue,
+            config,
+            size: winit::dpi::PhysicalSize::new(width, height),
+            sample_count,
+            depth_format: options.depth_format,
+            window: None,
+            offscreen_texture: Some(offscreen_texture),
+            options: options.clone(),
+            device_lost,
+        })
+    }
+
+    pub fn is_headless(&self) -> bool {
+        self.surface.is_none()
+    }
+
+    pub fn window(&self) -> &Window {
+        self.window
+            .as_ref()
+            .expect("headless InitWgpu has no window")
+    }
+
+    pub fn surface(&self) -> &wgpu::Surface<'static> {
+        self.surface
+            .as_ref()
+            .expect("headless InitWgpu has no surface")
+    }
+
+    pub fn configure_surface(&self) {
+        if let Some(surface) = &self.surface {
+            surface.configure(&self.device, &self.config);
+        }
+    }
+
+    pub fn resize(&mut self, width: u32, height: u32) {
+        if width > 0 && height > 0 {
+            self.config.width = width;
+            self.config.height = height;
+            self.size = winit::dpi::PhysicalSize::new(width, height);
+            match &self.surface {
+                // The surface needs to be reconfigured every time the window is resized.
+                Some(surface) => surface.configure(&self.device, &self.config),
+                None => {
+                    self.offscreen_texture =
+                        Some(create_offscreen_texture(&self.device, &self.config));
+                }
+            }
+        }
+    }
+
+    pub fn create_offscreen_view(&self) -> Option<wgpu::TextureView> {
+        self.offscreen_texture
+            .as_ref()
+            .map(|texture| texture.create_view(&wgpu::TextureViewDescriptor::default()))
+    }
+
+    // Returns the texture to render the next frame into, or None when the frame should be
+    // skipped. Suboptimal, outdated and lost surfaces are reconfigured or recreated here. When
+    // running headless the frame is the offscreen texture.
+    pub fn acquire_frame(&mut self) -> Result<Option<Frame>, wgpu::CreateSurfaceError> {
+        let Some(surface) = &self.surface else {
+            return Ok(self.offscreen_texture.clone().map(Frame::Offscreen));
+        };
+        match surface.get_current_texture() {
+            wgpu::CurrentSurfaceTexture::Success(surface_texture) => {
+                Ok(Some(Frame::Surface(surface_texture)))
+            }
+            wgpu::CurrentSurfaceTexture::Suboptimal(surface_texture) => {
+                self.configure_surface();
+                Ok(Some(Frame::Surface(surface_texture)))
+            }
+            wgpu::CurrentSurfaceTexture::Timeout
+            | wgpu::CurrentSurfaceTexture::Occluded
+            | wgpu::CurrentSurfaceTexture::Validation => Ok(None),
+            wgpu::CurrentSurfaceTexture::Outdated => {
+                self.configure_surface();
+                Ok(None)
+            }
+            wgpu::CurrentSurfaceTexture::Lost => {
+                // Only the surface is gone; the device and its resources are still valid.
+                self.recreate_surface()?;
+                Ok(None)
+            }
+        }
+    }
+
+    // Sample counts usable for MSAA with the current color format and the depth format.
+    pub fn supported_sample_counts(&self) -> Vec<u32> {
+        supported_sample_counts(&self.adapter, &[self.config.format, self.depth_format])
+    }
+
+    pub fn is_device_lost(&self) -> bool {
+        self.device_lost.load(Ordering::Relaxed)
+    }
+
+    // Recreates the surface after `CurrentSurfaceTexture::Lost`. The device is still valid,
+    // so pipelines, buffers and texture views created from it can be kept.
+    pub fn recreate_surface(&mut self) -> Result<(), wgpu::CreateSurfaceError> {
+        if let Some(window) = &self.window {
+            // Some platforms refuse a second surface while the old one is alive.
+            self.surface = None;
+            let surface = self.instance.create_surface(window.clone())?;
+            surface.configure(&self.device, &self.config);
+            self.surface = Some(surface);
+        }
+        Ok(())
+    }
+
+    // Requests a new adapter and device with the same options after the device was lost.
+    // Everything created from the old device (pipelines, buffers, bind groups, MSAA and depth
+    // views) is unusable afterwards and must be rebuilt by the caller.
+    pub async fn recreate_device(&mut self) -> Result<(), InitWgpuError> {
+        let mut options = self.options.clone();
+        options.present_mode = self.config.present_mode;
+        *self = match self.window.clone() {
+            Some(window) => {
+                // Some platforms refuse a second surface on the window while the old one is
+                // alive; it is put back when the new device can't be created.
+                let surface = self.surface.take();
+                match Self::try_init_wgpu_with_options(window, self.sample_count, &options).await {
+                    Ok(init) => init,
+                    Err(e) => {
+                        self.surface = surface;
+                        return Err(e);
+                    }
+                }
+            }
+            None => {
+                Self::try_init_headless_with_options(
+                    self.config.width,
+                    self.config.height,
+                    self.config.format,
+                    self.sample_count,
+                    &options,
+                )
+                .await?
+            }
+        };
+        Ok(())
+    }
+
+    // Present modes the surface supports; empty when running headless.
+    pub fn supported_present_modes(&self) -> Vec<wgpu::PresentMode> {
+        match &self.surface {
+            Some(surface) => surface.get_capabilities(&self.adapter).present_modes,
+            None => vec![],
+        }
+    }
+
+    // Reconfigures the surface with the given present mode, or the closest supported one,
+    // and returns the mode that was applied.
+    pub fn set_present_mode(&mut self, present_mode: wgpu::PresentMode) -> wgpu::PresentMode {
+        self.config.present_mode =
+            resolve_present_mode(present_mode, &self.supported_present_modes());
+        self.configure_surface();
+        self.config.present_mode
+    }
+
+    // Changes the sample count used by pipelines and render targets created from now on.
+    // Returns the supported counts when the request (after clamping) is not usable.
+    pub fn set_sample_count(&mut self, sample_count: u32) -> Result<u32, Vec<u32>> {
+        self.sample_count = check_sample_count(
+            &self.adapter,
+            self.config.format,
+            sample_count,
+            &self.options,
+        )?;
+        Ok(self.sample_count)
+    }
+
+    // Switches to the next supported present mode, e.g. to compare vsync and uncapped fps.
+    pub fn cycle_present_mode(&mut self) -> wgpu::PresentMode {
+        let modes = self.supported_present_modes();
+        let next = modes
+            .iter()
+            .position(|mode| *mode == self.config.present_mode)
+            .map_or(0, |i| (i + 1) % modes.len());
+        match modes.get(next) {
+            Some(mode) => self.set_present_mode(*mode),
+            None => self.config.present_mode,
+        }
+    }
+}
+
+pub enum Frame {
+    Surface(wgpu::SurfaceTexture),
+    Offscreen(wgpu::Texture),
+}
+
+impl Frame {
+    pub fn texture(&self) -> &wgpu::Texture {
+        match self {
+            Self::Surface(surface_texture) => &surface_texture.texture,
+            Self::Offscreen(texture) => texture,
+        }
+    }
+
+    pub fn create_view(&self) -> wgpu::TextureView {
+        self.texture()
+            .create_view(&wgpu::TextureViewDescriptor::default())
+    }
+
+    // Presents surface frames; offscreen frames stay in the offscreen texture.
+    pub fn present(self) {
+        if let Self::Surface(surface_texture) = self {
+            surface_texture.present();
+        }
+    }
+}
+
+// Logs device loss, out-of-memory and internal errors instead of panicking, and raises the
+// returned flag when the device can no longer be used. Validation errors still panic.
+fn watch_device(device: &wgpu::Device) -> Arc<AtomicBool> {
+    let device_lost = Arc::new(AtomicBool::new(false));
+
+    let lost = device_lost.clone();
+    device.set_device_lost_callback(move |reason, message| {
+        // `Destroyed` is only reported for devices destroyed on purpose.
+        if reason != wgpu::DeviceLostReason::Destroyed {
+            log::error!("device lost ({reason:?}): {message}");
+            lost.store(true, Ordering::Relaxed);
+        }
+    });
+
+    let lost = device_lost.clone();
+    device.on_uncaptured_error(Arc::new(move |error| match error {
+        // A bug in the calling code; fail loudly, as wgpu's default handler does.
+        wgpu::Error::Validation { .. } => panic!("wgpu error: {error}"),
+        wgpu::Error::OutOfMemory { source } => {
+            log::error!("out of memory: {source}");
+            lost.store(true, Ordering::Relaxed);
+        }
+        wgpu::Error::Internal { description, .. } => {
+            log::error!("internal error: {description}");
+            lost.store(true, Ordering::Relaxed);
+        }
+    }));
+
+    device_lost
+}
+
+// Sample counts the adapter supports for every one of the given formats.
+pub fn supported_sample_counts(
+    adapter: &wgpu::Adapter,
+    formats: &[wgpu::TextureFormat],
+) -> Vec<u32> {
+    let mut counts = vec![1, 2, 4, 8, 16];
+    for format in formats {
+        let flags = adapter.get_texture_format_features(*format).flags;
+        counts.retain(|count| flags.sample_count_supported(*count));
+    }
+    counts
+}
+
+// Returns the sample count to use, or the supported sample counts if the request is rejected.
+fn check_sample_count(
+    adapter: &wgpu::Adapter,
+    format: wgpu::TextureFormat,
+    requested: u32,
+    options: &InitWgpuOptions,
+) -> Result<u32, Vec<u32>> {
+    let supported = supported_sample_counts(adapter, &[format, options.depth_format]);
+    if supported.contains(&requested) {
+        return Ok(requested);
+    }
+    // A sample count of 1 is always supported.
+    match supported.iter().copied().filter(|c| *c < requested).max() {
+        Some(count) if options.clamp_sample_count => {
+            log::warn!("sample count {requested} is not supported, using {count}");
+            Ok(count)
+        }
+        _ => Err(supported),
+    }
+}
+
+fn resolve_present_mode(
+    requested: wgpu::PresentMode,
+    supported: &[wgpu::PresentMode],
+) -> wgpu::PresentMode {
+    match requested {
+        // wgpu falls back on its own for the Auto modes.
+        wgpu::PresentMode::AutoVsync | wgpu::PresentMode::AutoNoVsync => requested,
+        mode if supported.contains(&mode) => mode,
+        wgpu::PresentMode::Immediate | wgpu::PresentMode::Mailbox => {
+            log::warn!("present mode {requested:?} is not supported, using AutoNoVsync");
+            wgpu::PresentMode::AutoNoVsync
+        }
+        _ => {
+            // Fifo is the only present mode every surface is guaranteed to support.
+            log::warn!("present mode {requested:?} is not supported, using Fifo");
+            wgpu::PresentMode::Fifo
+        }
+    }
+}
+
+const OFFSCREEN_USAGES: wgpu::TextureUsages = wgpu::TextureUsages::RENDER_ATTACHMENT
+    .union(wgpu::TextureUsages::COPY_SRC)
+    .union(wgpu::TextureUsages::TEXTURE_BINDING);
+
+#[derive(Debug)]
+pub enum InitWgpuError {
+    // The window handle could not be turned into a surface.
+    CreateSurface(wgpu::CreateSurfaceError),
+    // No adapter on the enabled backends matched the request.
+    NoAdapter {
+        backends: wgpu::Backends,
+        source: wgpu::RequestAdapterError,
+    },
+    // None of the enumerated adapters matched the selector.
+    NoMatchingAdapter {
+        selector: AdapterSelector,
+        available: Vec<wgpu::AdapterInfo>,
+    },
+    // The MSAA sample count is not supported for the color and depth formats.
+    UnsupportedSampleCount {
+        adapter: wgpu::AdapterInfo,
+        requested: u32,
+        supported: Vec<u32>,
+    },
+    // The adapter lacks some of the requested features.
+    UnsupportedFeatures {
+        adapter: wgpu::AdapterInfo,
+        missing: wgpu::Features,
+    },
+    // The adapter cannot satisfy some of the requested limits, as (name, requested, supported).
+    UnsupportedLimits {
+        adapter: wgpu::AdapterInfo,
+        limits: Vec<(&'static str, u64, u64)>,
+    },
+    // The adapter was found but refused to create a logical device.
+    RequestDevice {
+        adapter: wgpu::AdapterInfo,
+        source: wgpu::RequestDeviceError,
+    },
+    // The adapter cannot present to the window surface.
+    SurfaceUnsupported {
+        adapter: wgpu::AdapterInfo,
+    },
+    // None of the candidate formats can be used as a render target on the adapter.
+    NoCompatibleFormat {
+        adapter: wgpu::AdapterInfo,
+        formats: Vec<wgpu::TextureFormat>,
+    },
+}
+
+impl fmt::Display for InitWgpuError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::CreateSurface(e) => write!(f, "failed to create surface: {e}"),
+            Self::NoAdapter { backends, source } => {
+                write!(f, "no suitable adapter found on {backends:?}: {source}")
+            }
+            Self::NoMatchingAdapter {
+                selector,
+                available,
+            } => {
+                write!(f, "no adapter matches {selector:?}; available:")?;
+                for info in available {
+                    write!(
+                        f,
+                        " [{} ({:?}, {:?})]",
+                        info.name, info.device_type, info.backend
+                    )?;
+                }
+                Ok(())
+            }
+            Self::UnsupportedSampleCount {
+                adapter,
+                requested,
+                supported,
+            } => write!(
+                f,
+                "sample count {requested} is not supported by adapter {} ({:?}); \
+                 supported sample counts: {supported:?}",
+                adapter.name, adapter.backend
+            ),
+            Self::UnsupportedFeatures { adapter, missing } => write!(
+                f,
+                "adapter {} ({:?}) does not support the features {missing:?}",
+                adapter.name, adapter.backend
+            ),
+            Self::UnsupportedLimits { adapter, limits } => {
+                write!(
+                    f,
+                    "adapter {} ({:?}) does not support the requested limits:",
+                    adapter.name, adapter.backend
+                )?;
+                for (name, requested, supported) in limits {
+                    write!(f, " {name} (requested {requested}, supported {supported})")?;
+                }
+                Ok(())
+            }
+            Self::RequestDevice { adapter, source } => write!(
+                f,
+                "failed to request device from {} ({:?}): {source}",
+                adapter.name, adapter.backend
+            ),
+            Self::SurfaceUnsupported { adapter } => write!(
+                f,
+                "adapter {} ({:?}) cannot present to the surface",
+                adapter.name, adapter.backend
+            ),
+            Self::NoCompatibleFormat { adapter, formats } => write!(
+                f,
+                "adapter {} ({:?}) supports none of the formats {formats:?}",
+                adapter.name, adapter.backend
+            ),
+        }
+    }
+}
+
+impl std::error::Error for InitWgpuError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::CreateSurface(e) => Some(e),
+            Self::NoAdapter { source, .. } => Some(source),
+            Self::RequestDevice { source, .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+
+async fn request_device(
+    instance: &wgpu::Instance,
+    options: &InitWgpuOptions,
+    compatible_surface: Option<&wgpu::Surface<'_>>,
+) -> Result<(wgpu::Adapter, wgpu::Device, wgpu::Queue), InitWgpuError> {
+    // Adapter: an explicit selector (or WGPU_ADAPTER, see `resolve_adapter`) wins over wgpu's
+    // own choice.
+    let adapter = match options.adapter.clone() {
+        AdapterSelector::Default => instance
+            .request_adapter(&wgpu::RequestAdapterOptions {
+                power_preference: options.power_preference,
+                compatible_surface,
+                force_fallback_adapter: options.force_fallback_adapter,
+            })
+            .await
+            .map_err(|source| InitWgpuError::NoAdapter {
+                backends: options.backends,
+                source,
+            })?,
+        selector => {
+            let adapters = enumerate_adapters(instance, options.backends, compatible_surface).await;
+            match adapters
+                .iter()
+                .position(|a| selector.matches(&a.get_info()))
+            {
+                Some(i) => adapters.into_iter().nth(i).unwrap(),
+                None => {
+                    return Err(InitWgpuError::NoMatchingAdapter {
+                        selector,
+                        available: adapters.iter().map(|a| a.get_info()).collect(),
+                    });
+                }
+            }
+        }
+    };
+
+    // Check the request up front so the error names what is missing.
+    let required_features = options.required_features | options.depth_format.required_features();
+    let missing = required_features - adapter.features();
+    if !missing.is_empty() {
+        return Err(InitWgpuError::UnsupportedFeatures {
+            adapter: adapter.get_info(),
+            missing,
+        });
+    }
+    let mut limits = vec![];
+    options.required_limits.check_limits_with_fail_fn(
+        &adapter.limits(),
+        false,
+        |name, requested, supported| limits.push((name, requested, supported)),
+    );
+    if !limits.is_empty() {
+        return Err(InitWgpuError::UnsupportedLimits {
+            adapter: adapter.get_info(),
+            limits,
+        });
+    }
+
+    // Logical Device and Queue
+    let (device, queue) = adapter
+        .request_device(&wgpu::DeviceDescriptor {
+            label: None,
+            required_features,
+            required_limits: options.required_limits.clone(),
+            ..Default::default()
+        })
+        .await
+        .map_err(|source| InitWgpuError::RequestDevice {
+            adapter: adapter.get_info(),
+            source,
+        })?;
+
+    Ok((adapter, device, queue))
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum AdapterSelector {
+    // Let wgpu pick using the power preference.
+    #[default]
+    Default,
+    // Case-insensitive substring of `AdapterInfo::name`.
+    Name(String),
+    DeviceType(wgpu::DeviceType),
+    Backend(wgpu::Backends),
+}
+
+impl AdapterSelector {
+    // Reads WGPU_ADAPTER, e.g. "name:rtx", "type:discrete", "backend:vulkan" or just "rtx".
+    pub fn from_env() -> Option<Self> {
+        let value = std::env::var("WGPU_ADAPTER")
+            .ok()
+            .filter(|v| !v.trim().is_empty())?;
+        let selector = Self::parse(&value);
+        if selector.is_none() {
+            log::warn!("ignoring invalid WGPU_ADAPTER value '{value}'");
+        }
+        selector
+    }
+
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        let (key, arg) = value.split_once(':').unwrap_or(("name", value));
+        let arg = arg.trim();
+        match key.trim().to_lowercase().as_str() {
+            "name" if !arg.is_empty() => Some(Self::Name(arg.to_string())),
+            "type" => match arg.to_lowercase().as_str() {
+                "discrete" => Some(Self::DeviceType(wgpu::DeviceType::DiscreteGpu)),
+                "integrated" => Some(Self::DeviceType(wgpu::DeviceType::IntegratedGpu)),
+                "virtual" => Some(Self::DeviceType(wgpu::DeviceType::VirtualGpu)),
+                "cpu" => Some(Self::DeviceType(wgpu::DeviceType::Cpu)),
+                "other" => Some(Self::DeviceType(wgpu::DeviceType::Other)),
+                _ => None,
+            },
+            "backend" => {
+                let backends = wgpu::Backends::from_comma_list(arg);
+                (!backends.is_empty()).then_some(Self::Backend(backends))
+            }
+            _ => None,
+        }
+    }
+
+    pub fn matches(&self, info: &wgpu::AdapterInfo) -> bool {
+        match self {
+            Self::Default => true,
+            Self::Name(name) => info.name.to_lowercase().contains(&name.to_lowercase()),
+            Self::DeviceType(device_type) => info.device_type == *device_type,
+            Self::Backend(backends) => backends.contains(info.backend.into()),
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub enum SurfaceFormatPreference {
+    // An sRGB format, so shaders can output linear colors and get gamma-correct results.
+    #[default]
+    Srgb,
+    // A non-sRGB format; shader output is written to the surface unchanged.
+    Linear,
+    // A 16-bit float or 10-bit format for HDR or wide-gamut output.
+    Hdr,
+    // The first supported format from the list; initialization fails if none is supported.
+    Explicit(Vec<wgpu::TextureFormat>),
+}
+
+impl SurfaceFormatPreference {
+    pub fn select(&self, available: &[wgpu::TextureFormat]) -> Option<wgpu::TextureFormat> {
+        match self {
+            Self::Srgb => available.iter().copied().find(|f| f.is_srgb()),
+            Self::Linear => available.iter().copied().find(|f| !f.is_srgb()),
+            Self::Hdr => [
+                wgpu::TextureFormat::Rgba16Float,
+                wgpu::TextureFormat::Rgb10a2Unorm,
+            ]
+            .into_iter()
+            .find(|f| available.contains(f)),
+            Self::Explicit(formats) => formats.iter().copied().find(|f| available.contains(f)),
+        }
+    }
+}
+
+// Lists the adapters on the given backends, keeping only those that can present to the
+// surface when one is given.
+pub async fn enumerate_adapters(
+    instance: &wgpu::Instance,
+    backends: wgpu::Backends,
+    compatible_surface: Option<&wgpu::Surface<'_>>,
+) -> Vec<wgpu::Adapter> {
+    instance
+        .enumerate_adapters(backends)
+        .await
+        .into_iter()
+        .filter(|adapter| compatible_surface.is_none_or(|s| adapter.is_surface_supported(s)))
+        .collect()
+}
+
+fn create_offscreen_texture(
+    device: &wgpu::Device,
+    config: &wgpu::SurfaceConfiguration,
+) -> wgpu::Texture {
+    device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width: config.width,
+            height: config.height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format: config.format,
+        usage: config.usage,
+        label: Some("Offscreen Texture"),
+        view_formats: &[],
+    })
+}
+
+// Gets a window app back on a device after the old one was lost. Only the InitWgpu of the lost
+// state is kept, so the new device is requested with the same options, sample count and
+// present mode. Failed attempts are retried every `retry_interval` with
+// ControlFlow::WaitUntil instead of spinning:
+//
+// if state.is_device_lost() {
+//     self.recovery.start(self.state.take().unwrap().init);
+// }
+// ..
+// fn about_to_wait(&mut self, event_loop: &ActiveEventLoop) {
+//     if let Some(init) = self.recovery.poll(event_loop) {
+//         self.state = Some(State::with_init(init));
+//     }
+// }
+pub struct DeviceRecovery {
+    lost: Option<InitWgpu>,
+    next_attempt: Instant,
+    pub retry_interval: Duration,
+}
+
+impl Default for DeviceRecovery {
+    fn default() -> Self {
+        Self {
+            lost: None,
+            next_attempt: Instant::now(),
+            retry_interval: Duration::from_secs(1),
+        }
+    }
+}
+
+impl DeviceRecovery {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    // Everything else created on the lost device should be dropped before this is called.
+    pub fn start(&mut self, init: InitWgpu) {
+        self.lost = Some(init);
+        self.next_attempt = Instant::now();
+    }
+
+    // Returns the InitWgpu on the new device once it could be created.
+    pub fn poll(&mut self, event_loop: &winit::event_loop::ActiveEventLoop) -> Option<InitWgpu> {
+        let mut init = self.lost.take()?;
+        if Instant::now() >= self.next_attempt {
+            match pollster::block_on(init.recreate_device()) {
+                Ok(()) => {
+                    event_loop.set_control_flow(winit::event_loop::ControlFlow::Wait);
+                    init.window().request_redraw();
+                    return Some(init);
+                }
+                Err(e) => {
+                    log::error!("failed to recreate the device: {e}");
+                    self.next_attempt = Instant::now() + self.retry_interval;
+                }
+            }
+        }
+        event_loop.set_control_flow(winit::event_loop::ControlFlow::WaitUntil(self.next_attempt));
+        self.lost = Some(init);
+        None
     }
 }
 // endregion: wgpu initialization
 
 // region: pipelines
 pub struct IRenderPipeline<'a> {
+    pub label: &'a str,
     pub shader: Option<&'a wgpu::ShaderModule>,
     pub vs_shader: Option<&'a wgpu::ShaderModule>,
+    // Without a fragment shader the pipeline only writes depth.
     pub fs_shader: Option<&'a wgpu::ShaderModule>,
     pub vertex_buffer_layout: &'a [wgpu::VertexBufferLayout<'a>],
     pub pipeline_layout: Option<&'a wgpu::PipelineLayout>,
     pub topology: wgpu::PrimitiveTopology,
     pub strip_index_format: Option<wgpu::IndexFormat>,
+    pub front_face: wgpu::FrontFace,
     pub cull_mode: Option<wgpu::Face>,
+    // Line and Point need Features::POLYGON_MODE_LINE and Features::POLYGON_MODE_POINT.
+    pub polygon_mode: wgpu::PolygonMode,
+    // Needs Features::DEPTH_CLIP_CONTROL.
+    pub unclipped_depth: bool,
+    // Needs Features::CONSERVATIVE_RASTERIZATION.
+    pub conservative: bool,
     pub is_depth_stencil: bool,
+    // Defaults to the depth format of InitWgpu.
+    pub depth_format: Option<wgpu::TextureFormat>,
+    pub depth_compare: wgpu::CompareFunction,
+    pub depth_write_enabled: bool,
+    pub stencil: wgpu::StencilState,
+    pub depth_bias: wgpu::DepthBiasState,
+    // Use one of the BLEND_* presets for transparency; None replaces the target color.
+    pub blend: Option<wgpu::BlendState>,
+    pub write_mask: wgpu::ColorWrites,
+    // Multiple render targets, one per `@location` of the fragment output, each with its own
+    // format and blend state. When empty, the pipeline has a single target in the surface
+    // format using `blend` and `write_mask`.
+    pub color_targets: &'a [Option<wgpu::ColorTargetState>],
+    // Defaults to the sample count of InitWgpu; set it for offscreen targets that differ.
+    pub sample_count: Option<u32>,
+    pub sample_mask: u64,
+    pub alpha_to_coverage_enabled: bool,
+    // Values for WGSL `override` declarations, shared by both stages.
+    pub constants: &'a [(&'a str, f64)],
     pub vs_entry: String,
     pub fs_entry: String,
 }
@@ -103,6 +999,7 @@ pub struct IRenderPipeline<'a> {
 impl Default for IRenderPipeline<'_> {
     fn default() -> Self {
         Self {
+            label: "Render Pipeline",
             shader: None,
             vs_shader: None,
             fs_shader: None,
@@ -110,8 +1007,24 @@ impl Default for IRenderPipeline<'_> {
             pipeline_layout: None,
             topology: wgpu::PrimitiveTopology::TriangleList,
             strip_index_format: None,
+            front_face: wgpu::FrontFace::Ccw,
             cull_mode: None,
+            polygon_mode: wgpu::PolygonMode::Fill,
+            unclipped_depth: false,
+            conservative: false,
             is_depth_stencil: true,
+            depth_format: None,
+            depth_compare: wgpu::CompareFunction::LessEqual,
+            depth_write_enabled: true,
+            stencil: wgpu::StencilState::default(),
+            depth_bias: wgpu::DepthBiasState::default(),
+            blend: None,
+            write_mask: wgpu::ColorWrites::ALL,
+            color_targets: &[],
+            sample_count: None,
+            sample_mask: !0,
+            alpha_to_coverage_enabled: false,
+            constants: &[],
             vs_entry: String::from("vs_main"),
             fs_entry: String::from("fs_main"),
         }
@@ -124,67 +1037,454 @@ impl IRenderPipeline<'_> {
             self.vs_shader = self.shader;
             self.fs_shader = self.shader;
         }
+        self.create(init, None)
+    }
 
-        let mut depth_stencil: Option<wgpu::DepthStencilState> = None;
-        if self.is_depth_stencil {
-            depth_stencil = Some(wgpu::DepthStencilState {
-                format: wgpu::TextureFormat::Depth24Plus,
-                depth_write_enabled: Some(true),
-                depth_compare: Some(wgpu::CompareFunction::LessEqual),
-                stencil: wgpu::StencilState::default(),
-                bias: wgpu::DepthBiasState::default(),
-            });
-        }
+    fn create(&self, init: &InitWgpu, cache: Option<&wgpu::PipelineCache>) -> wgpu::RenderPipeline {
+        let compilation_options = wgpu::PipelineCompilationOptions {
+            constants: self.constants,
+            ..Default::default()
+        };
+
+        let targets = self.targets(init);
+        let fragment = self.fs_module().map(|fs_shader| wgpu::FragmentState {
+            module: fs_shader,
+            entry_point: Some(&self.fs_entry),
+            targets: &targets,
+            compilation_options: compilation_options.clone(),
+        });
 
         init.device
             .create_render_pipeline(&wgpu::RenderPipelineDescriptor {
-                label: Some("Render Pipeline"),
-                layout: Some(&self.pipeline_layout.unwrap()),
+                label: Some(self.label),
+                layout: self.pipeline_layout,
                 vertex: wgpu::VertexState {
-                    module: &self.vs_shader.as_ref().unwrap(),
+                    module: self.vs_module().unwrap(),
                     entry_point: Some(&self.vs_entry),
-                    buffers: &self.vertex_buffer_layout,
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
+                    buffers: self.vertex_buffer_layout,
+                    compilation_options,
                 },
-                fragment: Some(wgpu::FragmentState {
-                    module: &self.fs_shader.as_ref().unwrap(),
-                    entry_point: Some(&self.fs_entry),
-                    targets: &[Some(init.config.format.into())],
-                    compilation_options: wgpu::PipelineCompilationOptions::default(),
-                }),
-                primitive: wgpu::PrimitiveState {
-                    topology: self.topology,
-                    strip_index_format: self.strip_index_format,
-                    ..Default::default()
-                },
-                depth_stencil,
-                multisample: wgpu::MultisampleState {
-                    count: init.sample_count,
+                fragment,
+                primitive: self.primitive(),
+                depth_stencil: self.depth_stencil(init),
+                multisample: self.multisample(init),
+                multiview_mask: None,
+                cache,
+            })
+    }
+
+    // `shader` takes precedence over the per-stage modules, as in `new`.
+    fn vs_module(&self) -> Option<&wgpu::ShaderModule> {
+        self.shader.or(self.vs_shader)
+    }
+
+    fn fs_module(&self) -> Option<&wgpu::ShaderModule> {
+        self.shader.or(self.fs_shader)
+    }
+
+    fn primitive(&self) -> wgpu::PrimitiveState {
+        wgpu::PrimitiveState {
+            topology: self.topology,
+            strip_index_format: self.strip_index_format,
+            front_face: self.front_face,
+            cull_mode: self.cull_mode,
+            unclipped_depth: self.unclipped_depth,
+            polygon_mode: self.polygon_mode,
+            conservative: self.conservative,
+        }
+    }
+
+    fn depth_stencil(&self, init: &InitWgpu) -> Option<wgpu::DepthStencilState> {
+        self.is_depth_stencil.then(|| wgpu::DepthStencilState {
+            format: self.depth_format.unwrap_or(init.depth_format),
+            depth_write_enabled: Some(self.depth_write_enabled),
+            depth_compare: Some(self.depth_compare),
+            stencil: self.stencil.clone(),
+            bias: self.depth_bias,
+        })
+    }
+
+    fn multisample(&self, init: &InitWgpu) -> wgpu::MultisampleState {
+        wgpu::MultisampleState {
+            count: self.sample_count.unwrap_or(init.sample_count),
+            mask: self.sample_mask,
+            alpha_to_coverage_enabled: self.alpha_to_coverage_enabled,
+        }
+    }
+
+    fn targets(&self, init: &InitWgpu) -> Vec<Option<wgpu::ColorTargetState>> {
+        if self.color_targets.is_empty() {
+            vec![Some(wgpu::ColorTargetState {
+                format: init.config.format,
+                blend: self.blend,
+                write_mask: self.write_mask,
+            })]
+        } else {
+            self.color_targets.to_vec()
+        }
+    }
+
+    // Everything that ends up in the pipeline descriptor except the label, with the InitWgpu
+    // defaults (formats, sample count) resolved.
+    pub fn key(&self, init: &InitWgpu) -> RenderPipelineKey {
+        RenderPipelineKey {
+            modules: [self.vs_module().cloned(), self.fs_module().cloned()],
+            entries: [self.vs_entry.clone(), self.fs_entry.clone()],
+            pipeline_layout: self.pipeline_layout.cloned(),
+            vertex_buffer_layout: self
+                .vertex_buffer_layout
+                .iter()
+                .map(|layout| {
+                    (
+                        layout.array_stride,
+                        layout.step_mode,
+                        layout.attributes.to_vec(),
+                    )
+                })
+                .collect(),
+            primitive: self.primitive(),
+            depth_stencil: self.depth_stencil(init),
+            multisample: self.multisample(init),
+            targets: self.targets(init),
+            constants: self
+                .constants
+                .iter()
+                .map(|(name, value)| (name.to_string(), value.to_bits()))
+                .collect(),
+        }
+    }
+}
+
+// An owned copy of an IRenderPipeline for RenderPipelineCache to compare. Shader modules and
+// layouts are compared by identity; holding them keeps that identity from being reused by a
+// new object while the key exists.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct RenderPipelineKey {
+    modules: [Option<wgpu::ShaderModule>; 2],
+    entries: [String; 2],
+    pipeline_layout: Option<wgpu::PipelineLayout>,
+    vertex_buffer_layout: Vec<(
+        wgpu::BufferAddress,
+        wgpu::VertexStepMode,
+        Vec<wgpu::VertexAttribute>,
+    )>,
+    primitive: wgpu::PrimitiveState,
+    depth_stencil: Option<wgpu::DepthStencilState>,
+    multisample: wgpu::MultisampleState,
+    targets: Vec<Option<wgpu::ColorTargetState>>,
+    constants: Vec<(String, u64)>,
+}
+
+// Blend presets for IRenderPipeline::blend.
+pub const BLEND_ALPHA: wgpu::BlendState = wgpu::BlendState::ALPHA_BLENDING;
+
+// For colors that are already multiplied by their alpha.
+pub const BLEND_PREMULTIPLIED: wgpu::BlendState = wgpu::BlendState::PREMULTIPLIED_ALPHA_BLENDING;
+
+// Adds the source color scaled by its alpha, e.g. for glows and particles.
+pub const BLEND_ADDITIVE: wgpu::BlendState = wgpu::BlendState {
+    color: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::SrcAlpha,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+    alpha: wgpu::BlendComponent {
+        src_factor: wgpu::BlendFactor::One,
+        dst_factor: wgpu::BlendFactor::One,
+        operation: wgpu::BlendOperation::Add,
+    },
+};
+// endregion: pipelines
+
+// region: compute
+pub struct IComputePipeline<'a> {
+    pub label: &'a str,
+    pub shader: Option<&'a wgpu::ShaderModule>,
+    // None derives the layout from the shader.
+    pub pipeline_layout: Option<&'a wgpu::PipelineLayout>,
+    // Values for WGSL `override` declarations, e.g. the workgroup size.
+    pub constants: &'a [(&'a str, f64)],
+    pub entry: String,
+}
+
+impl Default for IComputePipeline<'_> {
+    fn default() -> Self {
+        Self {
+            label: "Compute Pipeline",
+            shader: None,
+            pipeline_layout: None,
+            constants: &[],
+            entry: String::from("cs_main"),
+        }
+    }
+}
+
+impl IComputePipeline<'_> {
+    pub fn build(&self, init: &InitWgpu) -> wgpu::ComputePipeline {
+        init.device
+            .create_compute_pipeline(&wgpu::ComputePipelineDescriptor {
+                label: Some(self.label),
+                layout: self.pipeline_layout,
+                module: self.shader.as_ref().unwrap(),
+                entry_point: Some(&self.entry),
+                compilation_options: wgpu::PipelineCompilationOptions {
+                    constants: self.constants,
                     ..Default::default()
                 },
-                multiview_mask: None,
                 cache: None,
             })
     }
 }
-// endregion: pipelines
+
+// Number of workgroups needed to cover `count` invocations.
+pub fn workgroup_count(count: u32, workgroup_size: u32) -> u32 {
+    count.div_ceil(workgroup_size)
+}
+
+pub fn encode_dispatch(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::ComputePipeline,
+    bind_groups: &[&wgpu::BindGroup],
+    workgroups: [u32; 3],
+) {
+    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Compute Pass"),
+        timestamp_writes: None,
+    });
+    compute_pass.set_pipeline(pipeline);
+    for (i, bind_group) in bind_groups.iter().enumerate() {
+        compute_pass.set_bind_group(i as u32, *bind_group, &[]);
+    }
+    compute_pass.dispatch_workgroups(workgroups[0], workgroups[1], workgroups[2]);
+}
+
+// The workgroup counts are read on the GPU from `indirect_buffer` at `indirect_offset`, laid
+// out as `wgpu::util::DispatchIndirectArgs`. The buffer needs `BufferUsages::INDIRECT`.
+pub fn encode_dispatch_indirect(
+    encoder: &mut wgpu::CommandEncoder,
+    pipeline: &wgpu::ComputePipeline,
+    bind_groups: &[&wgpu::BindGroup],
+    indirect_buffer: &wgpu::Buffer,
+    indirect_offset: wgpu::BufferAddress,
+) {
+    let mut compute_pass = encoder.begin_compute_pass(&wgpu::ComputePassDescriptor {
+        label: Some("Compute Pass"),
+        timestamp_writes: None,
+    });
+    compute_pass.set_pipeline(pipeline);
+    for (i, bind_group) in bind_groups.iter().enumerate() {
+        compute_pass.set_bind_group(i as u32, *bind_group, &[]);
+    }
+    compute_pass.dispatch_workgroups_indirect(indirect_buffer, indirect_offset);
+}
+
+#[derive(Debug)]
+pub enum ReadBufferError {
+    // The buffer size is not a whole number of elements, or not a multiple of
+    // COPY_BUFFER_ALIGNMENT as buffer copies require.
+    Size { size: u64, element_size: usize },
+    Map(wgpu::BufferAsyncError),
+}
+
+impl fmt::Display for ReadBufferError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Size { size, element_size } => write!(
+                f,
+                "a buffer of {size} bytes can't be read as elements of {element_size} bytes; \
+                 the size must be a multiple of both {} and the element size",
+                wgpu::COPY_BUFFER_ALIGNMENT
+            ),
+            Self::Map(e) => write!(f, "failed to map the staging buffer: {e}"),
+        }
+    }
+}
+
+impl std::error::Error for ReadBufferError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Self::Map(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+// Copies `buffer` into a staging buffer and blocks until its contents are on the CPU.
+// The buffer needs `BufferUsages::COPY_SRC` and a size that is a multiple of both 4 and the
+// size of T.
+pub fn read_buffer<T: bytemuck::Pod>(
+    init: &InitWgpu,
+    buffer: &wgpu::Buffer,
+) -> Result<Vec<T>, ReadBufferError> {
+    let element_size = std::mem::size_of::<T>();
+    if element_size == 0
+        || !buffer.size().is_multiple_of(element_size as u64)
+        || !buffer.size().is_multiple_of(wgpu::COPY_BUFFER_ALIGNMENT)
+    {
+        return Err(ReadBufferError::Size {
+            size: buffer.size(),
+            element_size,
+        });
+    }
+
+    let staging_buffer = init.device.create_buffer(&wgpu::BufferDescriptor {
+        label: Some("Staging Buffer"),
+        size: buffer.size(),
+        usage: wgpu::BufferUsages::MAP_READ | wgpu::BufferUsages::COPY_DST,
+        mapped_at_creation: false,
+    });
+
+    let mut encoder = init
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+            label: Some("Readback Encoder"),
+        });
+    encoder.copy_buffer_to_buffer(buffer, 0, &staging_buffer, 0, buffer.size());
+    init.queue.submit(std::iter::once(encoder.finish()));
+
+    let (sender, receiver) = std::sync::mpsc::channel();
+    staging_buffer
+        .slice(..)
+        .map_async(wgpu::MapMode::Read, move |result| {
+            let _ = sender.send(result);
+        });
+    let _ = init.device.poll(wgpu::PollType::wait_indefinitely());
+    receiver
+        .recv()
+        .expect("map_async callback was not called")
+        .map_err(ReadBufferError::Map)?;
+
+    // Copies instead of casting in place, so the alignment of the mapping doesn't matter.
+    let data = bytemuck::pod_collect_to_vec(&staging_buffer.slice(..).get_mapped_range());
+    staging_buffer.unmap();
+    Ok(data)
+}
+// endregion: compute
+
+// region: pipeline cache
+// Hands out an existing RenderPipeline when an IRenderPipeline with the same key was built
+// before. With `with_disk_cache`, wgpu's PipelineCache data is also loaded from and saved to
+// disk so drivers can skip shader compilation on the next run. That needs
+// Features::PIPELINE_CACHE (Vulkan only) in InitWgpuOptions::required_features; without it
+// only the in-memory cache is used.
+#[derive(Default)]
+pub struct RenderPipelineCache {
+    pipelines: HashMap<RenderPipelineKey, wgpu::RenderPipeline>,
+    pub cache: Option<wgpu::PipelineCache>,
+    pub path: Option<PathBuf>,
+}
+
+impl RenderPipelineCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with_disk_cache(init: &InitWgpu, dir: impl AsRef<Path>) -> Self {
+        let mut pipeline_cache = Self::default();
+        if !init
+            .device
+            .features()
+            .contains(wgpu::Features::PIPELINE_CACHE)
+        {
+            log::warn!("pipeline cache data isn't persisted: Features::PIPELINE_CACHE is off");
+            return pipeline_cache;
+        }
+        let Some(file_name) = wgpu::util::pipeline_cache_key(&init.adapter.get_info()) else {
+            return pipeline_cache;
+        };
+
+        let path = dir.as_ref().join(file_name);
+        let data = std::fs::read(&path).ok();
+        // SAFETY: the data was written by `save` for this adapter, and `fallback` makes wgpu
+        // start from an empty cache if the driver rejects it.
+        pipeline_cache.cache = Some(unsafe {
+            init.device
+                .create_pipeline_cache(&wgpu::PipelineCacheDescriptor {
+                    label: Some("Pipeline Cache"),
+                    data: data.as_deref(),
+                    fallback: true,
+                })
+        });
+        pipeline_cache.path = Some(path);
+        pipeline_cache
+    }
+
+    pub fn get_or_create(
+        &mut self,
+        init: &InitWgpu,
+        ppl: &IRenderPipeline,
+    ) -> wgpu::RenderPipeline {
+        let key = ppl.key(init);
+        self.pipelines
+            .entry(key)
+            .or_insert_with(|| ppl.create(init, self.cache.as_ref()))
+            .clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.pipelines.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.pipelines.is_empty()
+    }
+
+    // Pipelines must be rebuilt after the sample count or surface format changes; their keys
+    // change too, so this only frees the old ones.
+    pub fn clear(&mut self) {
+        self.pipelines.clear();
+    }
+
+    // Writes the wgpu cache data next to the old file first, so a crash can't leave it
+    // half-written.
+    pub fn save(&self) -> std::io::Result<()> {
+        let (Some(cache), Some(path)) = (&self.cache, &self.path) else {
+            return Ok(());
+        };
+        let Some(data) = cache.get_data() else {
+            return Ok(());
+        };
+        if let Some(dir) = path.parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+        let temp_path = path.with_extension("tmp");
+        std::fs::write(&temp_path, data)?;
+        std::fs::rename(temp_path, path)
+    }
+}
+// endregion: pipeline cache
 
 // region: views and attachments
 pub fn create_color_attachment<'a>(
     texture_view: &'a wgpu::TextureView,
+) -> wgpu::RenderPassColorAttachment<'a> {
+    create_color_attachment_with(
+        texture_view,
+        wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+        wgpu::StoreOp::Store,
+    )
+}
+
+// `LoadOp::Load` keeps what earlier passes drew, e.g. for overlays or incremental drawing.
+// Surface textures don't keep their contents between frames, so only load them within a frame.
+pub fn create_color_attachment_with<'a>(
+    texture_view: &'a wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    store: wgpu::StoreOp,
 ) -> wgpu::RenderPassColorAttachment<'a> {
     wgpu::RenderPassColorAttachment {
         view: texture_view,
         depth_slice: None,
         resolve_target: None,
-        ops: wgpu::Operations {
-            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-            store: wgpu::StoreOp::Store,
-        },
+        ops: wgpu::Operations { load, store },
     }
 }
 
 pub fn create_msaa_texture_view(init: &InitWgpu) -> wgpu::TextureView {
+    create_msaa_texture_view_for(init, init.config.format)
+}
+
+fn create_msaa_texture_view_for(init: &InitWgpu, format: wgpu::TextureFormat) -> wgpu::TextureView {
     let msaa_texture = init.device.create_texture(&wgpu::TextureDescriptor {
         size: wgpu::Extent3d {
             width: init.config.width,
@@ -194,7 +1494,7 @@ pub fn create_msaa_texture_view(init: &InitWgpu) -> wgpu::TextureView {
         mip_level_count: 1,
         sample_count: init.sample_count,
         dimension: wgpu::TextureDimension::D2,
-        format: init.config.format,
+        format,
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         label: None,
         view_formats: &[],
@@ -203,20 +1503,110 @@ pub fn create_msaa_texture_view(init: &InitWgpu) -> wgpu::TextureView {
     msaa_texture.create_view(&wgpu::TextureViewDescriptor::default())
 }
 
+// Single-sampled targets at the surface size, one per format, e.g. for a G-buffer or a
+// picking-ID buffer. They can be bound as textures in a later pass or read back with
+// `capture::capture_frame`.
+pub fn create_color_target_views(
+    init: &InitWgpu,
+    formats: &[wgpu::TextureFormat],
+) -> Vec<wgpu::TextureView> {
+    formats
+        .iter()
+        .map(|format| {
+            let texture = init.device.create_texture(&wgpu::TextureDescriptor {
+                size: wgpu::Extent3d {
+                    width: init.config.width,
+                    height: init.config.height,
+                    depth_or_array_layers: 1,
+                },
+                mip_level_count: 1,
+                sample_count: 1,
+                dimension: wgpu::TextureDimension::D2,
+                format: *format,
+                usage: wgpu::TextureUsages::RENDER_ATTACHMENT
+                    | wgpu::TextureUsages::TEXTURE_BINDING
+                    | wgpu::TextureUsages::COPY_SRC,
+                label: Some("Color Target Texture"),
+                view_formats: &[],
+            });
+            texture.create_view(&wgpu::TextureViewDescriptor::default())
+        })
+        .collect()
+}
+
+// Multisampled counterparts of `create_color_target_views`. wgpu can't resolve integer
+// formats, so they are rejected here; render those targets with a sample count of 1.
+pub fn create_msaa_texture_views(
+    init: &InitWgpu,
+    formats: &[wgpu::TextureFormat],
+) -> Vec<wgpu::TextureView> {
+    formats
+        .iter()
+        .map(|format| {
+            assert!(
+                !matches!(
+                    format.sample_type(None, None),
+                    Some(wgpu::TextureSampleType::Uint | wgpu::TextureSampleType::Sint)
+                ),
+                "{format:?} is an integer format and can't be resolved from MSAA"
+            );
+            create_msaa_texture_view_for(init, *format)
+        })
+        .collect()
+}
+
+pub fn create_color_attachments<'a>(
+    texture_views: &[&'a wgpu::TextureView],
+) -> Vec<Option<wgpu::RenderPassColorAttachment<'a>>> {
+    texture_views
+        .iter()
+        .map(|view| Some(create_color_attachment(view)))
+        .collect()
+}
+
+pub fn create_msaa_color_attachments<'a>(
+    texture_views: &[&'a wgpu::TextureView],
+    msaa_views: &[&'a wgpu::TextureView],
+) -> Vec<Option<wgpu::RenderPassColorAttachment<'a>>> {
+    assert_eq!(
+        texture_views.len(),
+        msaa_views.len(),
+        "every color target needs its own MSAA view"
+    );
+    texture_views
+        .iter()
+        .zip(msaa_views)
+        .map(|(view, msaa_view)| Some(create_msaa_color_attachment(view, msaa_view)))
+        .collect()
+}
+
 pub fn create_msaa_color_attachment<'a>(
     texture_view: &'a wgpu::TextureView,
     msaa_view: &'a wgpu::TextureView,
+) -> wgpu::RenderPassColorAttachment<'a> {
+    create_msaa_color_attachment_with(
+        texture_view,
+        msaa_view,
+        wgpu::LoadOp::Clear(wgpu::Color::BLACK),
+        // Storing pre-resolve MSAA data is unnecessary if it isn't used later.
+        // On tile-based GPU, avoid store can reduce your app's memory footprint.
+        wgpu::StoreOp::Discard,
+    )
+}
+
+// `store` applies to the MSAA texture; the resolved result is always written to
+// `texture_view`. A later pass that loads the MSAA view needs `StoreOp::Store` here.
+pub fn create_msaa_color_attachment_with<'a>(
+    texture_view: &'a wgpu::TextureView,
+    msaa_view: &'a wgpu::TextureView,
+    load: wgpu::LoadOp<wgpu::Color>,
+    store: wgpu::StoreOp,
 ) -> wgpu::RenderPassColorAttachment<'a> {
     wgpu::RenderPassColorAttachment {
         view: msaa_view,
         depth_slice: None,
         resolve_target: Some(texture_view),
-        ops: wgpu::Operations {
-            load: wgpu::LoadOp::Clear(wgpu::Color::BLACK),
-            // Storing pre-resolve MSAA data is unnecessary if it isn't used later.
-            // On tile-based GPU, avoid store can reduce your app's memory footprint.
-            store: wgpu::StoreOp::Discard,
-        },
+        ops: wgpu::Operations { load, store },
     }
 }
 
@@ -230,7 +1620,7 @@ pub fn create_depth_view(init: &InitWgpu) -> wgpu::TextureView {
         mip_level_count: 1,
         sample_count: init.sample_count,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Depth24Plus,
+        format: init.depth_format,
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT,
         label: None,
         view_formats: &[],
@@ -242,17 +1632,361 @@ pub fn create_depth_view(init: &InitWgpu) -> wgpu::TextureView {
 pub fn create_depth_stencil_attachment<'a>(
     depth_view: &'a wgpu::TextureView,
 ) -> wgpu::RenderPassDepthStencilAttachment<'a> {
+    create_depth_stencil_attachment_with(depth_view, wgpu::CompareFunction::LessEqual, false)
+}
+
+// Clears depth to the far value for the compare function (0.0 for reversed-Z) and adds stencil
+// ops when the view has a stencil aspect. Set `store` when a later pass reads the values.
+pub fn create_depth_stencil_attachment_with<'a>(
+    depth_view: &'a wgpu::TextureView,
+    depth_compare: wgpu::CompareFunction,
+    store: bool,
+) -> wgpu::RenderPassDepthStencilAttachment<'a> {
+    let format = depth_view.texture().format();
+    let store = if store {
+        wgpu::StoreOp::Store
+    } else {
+        wgpu::StoreOp::Discard
+    };
+    let clear_depth = match depth_compare {
+        wgpu::CompareFunction::Greater | wgpu::CompareFunction::GreaterEqual => 0.0,
+        _ => 1.0,
+    };
+
     wgpu::RenderPassDepthStencilAttachment {
         view: depth_view,
-        depth_ops: Some(wgpu::Operations {
-            load: wgpu::LoadOp::Clear(1.0),
-            store: wgpu::StoreOp::Discard,
+        depth_ops: format.has_depth_aspect().then_some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(clear_depth),
+            store,
+        }),
+        stencil_ops: format.has_stencil_aspect().then_some(wgpu::Operations {
+            load: wgpu::LoadOp::Clear(0),
+            store,
         }),
-        stencil_ops: None,
     }
 }
 
+// The MSAA and depth textures that go with the surface (or offscreen) texture. They are
+// recreated by `resize`, and `color_attachment` renders into the MSAA view and resolves to the
+// frame's view when the sample count is above 1. The color attachment is cleared to
+// `clear_color` unless `load` is set:
+//
+// let color_attachment = target.color_attachment(&view);
+// encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+//     color_attachments: &[Some(color_attachment)],
+//     depth_stencil_attachment: target.depth_stencil_attachment(),
+//     ..
+// });
+pub struct RenderTarget {
+    pub msaa_view: Option<wgpu::TextureView>,
+    pub depth_view: Option<wgpu::TextureView>,
+    pub sample_count: u32,
+    pub clear_color: wgpu::Color,
+    // Keeps the MSAA samples of the previous frame instead of clearing, for drawing that
+    // accumulates over frames. Surface textures start every frame with undefined contents, so
+    // without MSAA the view is still cleared.
+    pub load: bool,
+}
+
+impl RenderTarget {
+    pub fn new(init: &InitWgpu) -> Self {
+        Self::create(init, true)
+    }
+
+    pub fn without_depth(init: &InitWgpu) -> Self {
+        Self::create(init, false)
+    }
+
+    fn create(init: &InitWgpu, has_depth: bool) -> Self {
+        Self {
+            msaa_view: (init.sample_count > 1).then(|| create_msaa_texture_view(init)),
+            depth_view: has_depth.then(|| create_depth_view(init)),
+            sample_count: init.sample_count,
+            clear_color: wgpu::Color::BLACK,
+            load: false,
+        }
+    }
+
+    // Call after `InitWgpu::resize`.
+    pub fn resize(&mut self, init: &InitWgpu) {
+        *self = Self {
+            clear_color: self.clear_color,
+            load: self.load,
+            ..Self::create(init, self.depth_view.is_some())
+        };
+    }
+
+    // Changes the sample count of `init` and recreates the views. Pipelines built with the old
+    // count no longer match the attachments and must be rebuilt by the caller.
+    pub fn set_sample_count(
+        &mut self,
+        init: &mut InitWgpu,
+        sample_count: u32,
+    ) -> Result<u32, Vec<u32>> {
+        let sample_count = init.set_sample_count(sample_count)?;
+        self.resize(init);
+        Ok(sample_count)
+    }
+
+    // Switches to the next supported sample count, wrapping around to 1.
+    pub fn cycle_sample_count(&mut self, init: &mut InitWgpu) -> u32 {
+        let counts = init.supported_sample_counts();
+        let next = counts
+            .iter()
+            .position(|count| *count == self.sample_count)
+            .map_or(0, |i| (i + 1) % counts.len());
+        match counts.get(next) {
+            Some(count) => self
+                .set_sample_count(init, *count)
+                .unwrap_or(self.sample_count),
+            None => self.sample_count,
+        }
+    }
+
+    pub fn color_attachment<'a>(
+        &'a self,
+        view: &'a wgpu::TextureView,
+    ) -> wgpu::RenderPassColorAttachment<'a> {
+        let keep_samples = self.load && self.msaa_view.is_some();
+        let load = if keep_samples {
+            wgpu::LoadOp::Load
+        } else {
+            wgpu::LoadOp::Clear(self.clear_color)
+        };
+        // The MSAA samples only have to be stored when the next frame loads them. Use
+        // `color_attachment_with` for a pass that a later pass loads from.
+        let store = if self.msaa_view.is_some() && !keep_samples {
+            wgpu::StoreOp::Discard
+        } else {
+            wgpu::StoreOp::Store
+        };
+        self.color_attachment_with(view, load, store)
+    }
+
+    pub fn color_attachment_with<'a>(
+        &'a self,
+        view: &'a wgpu::TextureView,
+        load: wgpu::LoadOp<wgpu::Color>,
+        store: wgpu::StoreOp,
+    ) -> wgpu::RenderPassColorAttachment<'a> {
+        match &self.msaa_view {
+            Some(msaa_view) => create_msaa_color_attachment_with(view, msaa_view, load, store),
+            None => create_color_attachment_with(view, load, store),
+        }
+    }
+
+    pub fn depth_stencil_attachment(&self) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        self.depth_view
+            .as_ref()
+            .map(create_depth_stencil_attachment)
+    }
+}
+
+// A color texture rendered by one pass and sampled by another, for minimaps, mirrors,
+// picture-in-picture views and post-processing chains. With `sample_count` above 1 the pass
+// renders into an MSAA view that resolves into `view`; `depth_format` adds a depth view.
+// Pipelines drawing into it need a matching `color_targets` entry and `sample_count`.
+pub struct IRenderTexture<'a> {
+    pub label: &'a str,
+    pub width: u32,
+    pub height: u32,
+    pub format: wgpu::TextureFormat,
+    pub sample_count: u32,
+    pub depth_format: Option<wgpu::TextureFormat>,
+    // Use FilterMode::Nearest for formats that are not filterable, e.g. integer formats.
+    pub filter: wgpu::FilterMode,
+    pub address_mode: wgpu::AddressMode,
+}
+
+impl Default for IRenderTexture<'_> {
+    fn default() -> Self {
+        Self {
+            label: "Render Texture",
+            width: 512,
+            height: 512,
+            format: wgpu::TextureFormat::Rgba8UnormSrgb,
+            sample_count: 1,
+            depth_format: None,
+            filter: wgpu::FilterMode::Linear,
+            address_mode: wgpu::AddressMode::ClampToEdge,
+        }
+    }
+}
+
+impl IRenderTexture<'_> {
+    pub fn build(&self, init: &InitWgpu) -> RenderTexture {
+        let texture = create_texture_2d(
+            init,
+            self.label,
+            (self.width, self.height),
+            self.format,
+            1,
+            wgpu::TextureUsages::RENDER_ATTACHMENT
+                | wgpu::TextureUsages::TEXTURE_BINDING
+                | wgpu::TextureUsages::COPY_SRC,
+        );
+        let msaa_view = (self.sample_count > 1).then(|| {
+            create_texture_2d(
+                init,
+                self.label,
+                (self.width, self.height),
+                self.format,
+                self.sample_count,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+        let depth_view = self.depth_format.map(|format| {
+            create_texture_2d(
+                init,
+                self.label,
+                (self.width, self.height),
+                format,
+                self.sample_count,
+                wgpu::TextureUsages::RENDER_ATTACHMENT,
+            )
+            .create_view(&wgpu::TextureViewDescriptor::default())
+        });
+
+        RenderTexture {
+            label: self.label.to_string(),
+            view: texture.create_view(&wgpu::TextureViewDescriptor::default()),
+            texture,
+            msaa_view,
+            depth_view,
+            sampler: create_sampler(init, self.filter, self.address_mode),
+            clear_color: wgpu::Color::BLACK,
+            load: false,
+        }
+    }
+}
+
+pub struct RenderTexture {
+    // Reused for the textures `resize` creates.
+    pub label: String,
+    pub texture: wgpu::Texture,
+    // The single-sampled view to bind in the passes that read the texture.
+    pub view: wgpu::TextureView,
+    pub msaa_view: Option<wgpu::TextureView>,
+    pub depth_view: Option<wgpu::TextureView>,
+    pub sampler: wgpu::Sampler,
+    // Cleared to `clear_color` at the start of each pass unless `load` is set, as with
+    // `RenderTarget`.
+    pub clear_color: wgpu::Color,
+    pub load: bool,
+}
+
+impl RenderTexture {
+    pub fn format(&self) -> wgpu::TextureFormat {
+        self.texture.format()
+    }
+
+    pub fn sample_count(&self) -> u32 {
+        self.msaa_view
+            .as_ref()
+            .map_or(1, |view| view.texture().sample_count())
+    }
+
+    pub fn color_attachment(&self) -> wgpu::RenderPassColorAttachment<'_> {
+        let load = if self.load {
+            wgpu::LoadOp::Load
+        } else {
+            wgpu::LoadOp::Clear(self.clear_color)
+        };
+        match &self.msaa_view {
+            Some(msaa_view) => {
+                create_msaa_color_attachment_with(&self.view, msaa_view, load, wgpu::StoreOp::Store)
+            }
+            None => create_color_attachment_with(&self.view, load, wgpu::StoreOp::Store),
+        }
+    }
+
+    pub fn depth_stencil_attachment(&self) -> Option<wgpu::RenderPassDepthStencilAttachment<'_>> {
+        self.depth_view
+            .as_ref()
+            .map(create_depth_stencil_attachment)
+    }
+
+    // Recreates the textures at a new size with the same formats and sample count. Bind groups
+    // that reference `view` must be rebuilt afterwards.
+    pub fn resize(&mut self, init: &InitWgpu, width: u32, height: u32) {
+        let resized = IRenderTexture {
+            label: &self.label,
+            width,
+            height,
+            format: self.format(),
+            sample_count: self.sample_count(),
+            depth_format: self.depth_view.as_ref().map(|view| view.texture().format()),
+            ..Default::default()
+        }
+        .build(init);
+        *self = RenderTexture {
+            sampler: self.sampler.clone(),
+            clear_color: self.clear_color,
+            load: self.load,
+            ..resized
+        };
+    }
+}
+
+pub fn create_sampler(
+    init: &InitWgpu,
+    filter: wgpu::FilterMode,
+    address_mode: wgpu::AddressMode,
+) -> wgpu::Sampler {
+    init.device.create_sampler(&wgpu::SamplerDescriptor {
+        label: Some("Sampler"),
+        address_mode_u: address_mode,
+        address_mode_v: address_mode,
+        address_mode_w: address_mode,
+        mag_filter: filter,
+        min_filter: filter,
+        ..Default::default()
+    })
+}
+
+fn create_texture_2d(
+    init: &InitWgpu,
+    label: &str,
+    (width, height): (u32, u32),
+    format: wgpu::TextureFormat,
+    sample_count: u32,
+    usage: wgpu::TextureUsages,
+) -> wgpu::Texture {
+    init.device.create_texture(&wgpu::TextureDescriptor {
+        size: wgpu::Extent3d {
+            width,
+            height,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage,
+        label: Some(label),
+        view_formats: &[],
+    })
+}
+
+// A Depth32Float texture that later passes sample, so it is always single-sampled whatever
+// the MSAA setting. See the shadow module.
 pub fn create_shadow_texture_view(init: &InitWgpu, width: u32, height: u32) -> wgpu::TextureView {
+    create_shadow_texture_view_with(init, width, height, wgpu::TextureFormat::Depth32Float)
+}
+
+// `format` must be a depth format without stencil, so the whole view can be bound as a
+// texture_depth_2d.
+pub fn create_shadow_texture_view_with(
+    init: &InitWgpu,
+    width: u32,
+    height: u32,
+    format: wgpu::TextureFormat,
+) -> wgpu::TextureView {
+    assert!(
+        format.is_depth_stencil_format() && !format.has_stencil_aspect(),
+        "shadow maps need a depth format without stencil, got {format:?}"
+    );
     let shadow_depth_texture = init.device.create_texture(&wgpu::TextureDescriptor {
         size: wgpu::Extent3d {
             width,
@@ -260,11 +1994,11 @@ pub fn create_shadow_texture_view(init: &InitWgpu, width: u32, height: u32) -> w
             depth_or_array_layers: 1,
         },
         mip_level_count: 1,
-        sample_count: init.sample_count,
+        sample_count: 1,
         dimension: wgpu::TextureDimension::D2,
-        format: wgpu::TextureFormat::Depth24Plus,
+        format,
         usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::TEXTURE_BINDING,
-        label: None,
+        label: Some("Shadow Texture"),
         view_formats: &[],
     });
 
@@ -448,6 +2182,243 @@ pub fn create_bind_group(
 
     (layout, bind_group)
 }
+
+// Builds a bind group and its layout from typed entries with explicit binding indices:
+//
+// let (layout, bind_group) = BindGroupBuilder::new("Uniform Bind Group")
+//     .uniform(0, wgpu::ShaderStages::VERTEX, &mvp_buffer)
+//     .texture(1, wgpu::ShaderStages::FRAGMENT, &view, sample_type, dimension)
+//     .sampler(2, wgpu::ShaderStages::FRAGMENT, &sampler, wgpu::SamplerBindingType::Filtering)
+//     .build(&device);
+pub struct BindGroupBuilder<'a> {
+    pub label: &'a str,
+    layout_entries: Vec<wgpu::BindGroupLayoutEntry>,
+    entries: Vec<wgpu::BindGroupEntry<'a>>,
+}
+
+impl<'a> BindGroupBuilder<'a> {
+    pub fn new(label: &'a str) -> Self {
+        Self {
+            label,
+            layout_entries: vec![],
+            entries: vec![],
+        }
+    }
+
+    // Adds any entry; the typed methods below cover the common cases.
+    pub fn entry(
+        mut self,
+        layout_entry: wgpu::BindGroupLayoutEntry,
+        resource: wgpu::BindingResource<'a>,
+    ) -> Self {
+        assert!(
+            self.layout_entries
+                .iter()
+                .all(|e| e.binding != layout_entry.binding),
+            "{}: binding {} is used twice",
+            self.label,
+            layout_entry.binding
+        );
+        self.entries.push(wgpu::BindGroupEntry {
+            binding: layout_entry.binding,
+            resource,
+        });
+        self.layout_entries.push(layout_entry);
+        self
+    }
+
+    pub fn uniform(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        buffer: &'a wgpu::Buffer,
+    ) -> Self {
+        self.buffer(
+            binding,
+            visibility,
+            wgpu::BufferBindingType::Uniform,
+            false,
+            buffer.as_entire_buffer_binding(),
+        )
+    }
+
+    pub fn storage(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        buffer: &'a wgpu::Buffer,
+        read_only: bool,
+    ) -> Self {
+        self.buffer(
+            binding,
+            visibility,
+            wgpu::BufferBindingType::Storage { read_only },
+            false,
+            buffer.as_entire_buffer_binding(),
+        )
+    }
+
+    // With `has_dynamic_offset`, `resource.size` is the size of one element and the offset is
+    // passed to `set_bind_group`. The minimum binding size is taken from `resource.size`, so
+    // wgpu checks it against the shader when the pipeline is created instead of at draw time.
+    pub fn buffer(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        ty: wgpu::BufferBindingType,
+        has_dynamic_offset: bool,
+        resource: wgpu::BufferBinding<'a>,
+    ) -> Self {
+        self.entry(
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: wgpu::BindingType::Buffer {
+                    ty,
+                    has_dynamic_offset,
+                    min_binding_size: resource.size,
+                },
+                count: None,
+            },
+            wgpu::BindingResource::Buffer(resource),
+        )
+    }
+
+    pub fn texture(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        view: &'a wgpu::TextureView,
+        sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Self {
+        let multisampled = view.texture().sample_count() > 1;
+        self.entry(
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    sample_type,
+                    view_dimension,
+                    multisampled,
+                },
+                count: None,
+            },
+            wgpu::BindingResource::TextureView(view),
+        )
+    }
+
+    pub fn sampler(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        sampler: &'a wgpu::Sampler,
+        ty: wgpu::SamplerBindingType,
+    ) -> Self {
+        self.entry(
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: wgpu::BindingType::Sampler(ty),
+                count: None,
+            },
+            wgpu::BindingResource::Sampler(sampler),
+        )
+    }
+
+    pub fn storage_texture(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        view: &'a wgpu::TextureView,
+        access: wgpu::StorageTextureAccess,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Self {
+        let format = view.texture().format();
+        self.entry(
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: wgpu::BindingType::StorageTexture {
+                    access,
+                    format,
+                    view_dimension,
+                },
+                count: None,
+            },
+            wgpu::BindingResource::TextureView(view),
+        )
+    }
+
+    // binding_array<texture_2d<f32>, N>; needs Features::TEXTURE_BINDING_ARRAY.
+    pub fn texture_array(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        views: &'a [&'a wgpu::TextureView],
+        sample_type: wgpu::TextureSampleType,
+        view_dimension: wgpu::TextureViewDimension,
+    ) -> Self {
+        self.entry(
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: wgpu::BindingType::Texture {
+                    sample_type,
+                    view_dimension,
+                    multisampled: false,
+                },
+                count: std::num::NonZeroU32::new(views.len() as u32),
+            },
+            wgpu::BindingResource::TextureViewArray(views),
+        )
+    }
+
+    // binding_array<sampler, N>; needs Features::TEXTURE_BINDING_ARRAY.
+    pub fn sampler_array(
+        self,
+        binding: u32,
+        visibility: wgpu::ShaderStages,
+        samplers: &'a [&'a wgpu::Sampler],
+        ty: wgpu::SamplerBindingType,
+    ) -> Self {
+        self.entry(
+            wgpu::BindGroupLayoutEntry {
+                binding,
+                visibility,
+                ty: wgpu::BindingType::Sampler(ty),
+                count: std::num::NonZeroU32::new(samplers.len() as u32),
+            },
+            wgpu::BindingResource::SamplerArray(samplers),
+        )
+    }
+
+    pub fn build_layout(&self, device: &wgpu::Device) -> wgpu::BindGroupLayout {
+        device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
+            label: Some(self.label),
+            entries: &self.layout_entries,
+        })
+    }
+
+    // For bind groups that share a layout built earlier with `build_layout`.
+    pub fn build_with_layout(
+        &self,
+        device: &wgpu::Device,
+        layout: &wgpu::BindGroupLayout,
+    ) -> wgpu::BindGroup {
+        device.create_bind_group(&wgpu::BindGroupDescriptor {
+            label: Some(self.label),
+            layout,
+            entries: &self.entries,
+        })
+    }
+
+    pub fn build(&self, device: &wgpu::Device) -> (wgpu::BindGroupLayout, wgpu::BindGroup) {
+        let layout = self.build_layout(device);
+        let bind_group = self.build_with_layout(device, &layout);
+        (layout, bind_group)
+    }
+}
 // endregion: bind groups
 
 // region: utility
@@ -0,0 +1,75 @@
+mod harness;
+
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::wgpu_simplified as ws;
+
+const CLEAR_COLOR: wgpu::Color = wgpu::Color {
+    r: 1.0,
+    g: 0.0,
+    b: 0.5,
+    a: 1.0,
+};
+
+// Clears a small offscreen target in `format` and reads it back.
+fn capture_cleared(init: &ws::InitWgpu, format: wgpu::TextureFormat) -> capture::CapturedImage {
+    let texture = init.device.create_texture(&wgpu::TextureDescriptor {
+        label: Some("Capture Target"),
+        size: wgpu::Extent3d {
+            width: 4,
+            height: 2,
+            depth_or_array_layers: 1,
+        },
+        mip_level_count: 1,
+        sample_count: 1,
+        dimension: wgpu::TextureDimension::D2,
+        format,
+        usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+        view_formats: &[],
+    });
+    let view = texture.create_view(&wgpu::TextureViewDescriptor::default());
+
+    let mut encoder = init
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+        label: None,
+        color_attachments: &[Some(ws::create_color_attachment_with(
+            &view,
+            wgpu::LoadOp::Clear(CLEAR_COLOR),
+            wgpu::StoreOp::Store,
+        ))],
+        depth_stencil_attachment: None,
+        occlusion_query_set: None,
+        timestamp_writes: None,
+        multiview_mask: None,
+    });
+    init.queue.submit(std::iter::once(encoder.finish()));
+
+    capture::capture_frame(init, &texture).unwrap()
+}
+
+#[test]
+fn capture_supported_formats() {
+    let Some(init) = harness::init(1) else {
+        return;
+    };
+    // sRGB and float targets come back sRGB-encoded, linear unorm targets unchanged
+    let cases = [
+        (wgpu::TextureFormat::Rgba8Unorm, 128),
+        (wgpu::TextureFormat::Rgba8UnormSrgb, 188),
+        (wgpu::TextureFormat::Bgra8Unorm, 128),
+        (wgpu::TextureFormat::Bgra8UnormSrgb, 188),
+        (wgpu::TextureFormat::Rgba16Float, 188),
+        (wgpu::TextureFormat::Rgb10a2Unorm, 128),
+    ];
+    for (format, blue) in cases {
+        let image = capture_cleared(&init, format);
+        assert_eq!((image.width, image.height), (4, 2), "{format:?}");
+        assert_eq!(image.rgba.len(), 4 * 2 * 4, "{format:?}");
+        for pixel in image.rgba.chunks_exact(4) {
+            let expected = [255, 0, blue, 255];
+            let close = pixel.iter().zip(expected).all(|(&a, b)| a.abs_diff(b) <= 1);
+            assert!(close, "{format:?}: expected {expected:?}, got {pixel:?}");
+        }
+    }
+}
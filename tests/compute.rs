@@ -0,0 +1,79 @@
+mod harness;
+
+use wgpu::util::DeviceExt;
+use wgpu_fundamentals::wgpu_simplified as ws;
+
+const SHADER: &str = "
+override WORKGROUP_SIZE: u32 = 64;
+
+@group(0) @binding(0) var<storage, read_write> values: array<u32>;
+
+@compute @workgroup_size(WORKGROUP_SIZE)
+fn cs_main(@builtin(global_invocation_id) id: vec3u) {
+    if id.x < arrayLength(&values) {
+        values[id.x] = values[id.x] * 2u + 1u;
+    }
+}
+";
+
+#[test]
+fn compute_dispatch() {
+    let Some(init) = harness::init(1) else {
+        return;
+    };
+    let shader = init
+        .device
+        .create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        });
+    let workgroup_size = 32;
+    let pipeline = ws::IComputePipeline {
+        shader: Some(&shader),
+        constants: &[("WORKGROUP_SIZE", workgroup_size as f64)],
+        ..Default::default()
+    }
+    .build(&init);
+
+    // not a multiple of the workgroup size, so the last workgroup is partly idle
+    let input: Vec<u32> = (0..100).collect();
+    let buffer = init
+        .device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: bytemuck::cast_slice(&input),
+            usage: wgpu::BufferUsages::STORAGE | wgpu::BufferUsages::COPY_SRC,
+        });
+    let bind_group = init.device.create_bind_group(&wgpu::BindGroupDescriptor {
+        label: None,
+        layout: &pipeline.get_bind_group_layout(0),
+        entries: &[wgpu::BindGroupEntry {
+            binding: 0,
+            resource: buffer.as_entire_binding(),
+        }],
+    });
+
+    // once directly, then again with the same workgroup count read from a buffer
+    let workgroups = ws::workgroup_count(input.len() as u32, workgroup_size);
+    let indirect_buffer = init
+        .device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents: wgpu::util::DispatchIndirectArgs {
+                x: workgroups,
+                y: 1,
+                z: 1,
+            }
+            .as_bytes(),
+            usage: wgpu::BufferUsages::INDIRECT,
+        });
+    let mut encoder = init
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    ws::encode_dispatch(&mut encoder, &pipeline, &[&bind_group], [workgroups, 1, 1]);
+    ws::encode_dispatch_indirect(&mut encoder, &pipeline, &[&bind_group], &indirect_buffer, 0);
+    init.queue.submit(std::iter::once(encoder.finish()));
+
+    let expected: Vec<u32> = input.iter().map(|v| (v * 2 + 1) * 2 + 1).collect();
+    assert_eq!(ws::read_buffer::<u32>(&init, &buffer).unwrap(), expected);
+}
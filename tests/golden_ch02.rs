@@ -0,0 +1,42 @@
+mod harness;
+
+use wgpu_fundamentals::wgpu_simplified as ws;
+
+// ch02/common/state.rs, shared by triangle_vertex_color and triangle_primitive
+#[allow(dead_code)]
+#[path = "../ch02/common/state.rs"]
+mod triangle;
+
+#[allow(dead_code)]
+#[path = "../ch02/04_line_msaa/state.rs"]
+mod line_msaa;
+
+// as in ch02/01_triangle_vertex_color/main.rs
+fn vertex_color_state(init: ws::InitWgpu) -> triangle::State {
+    let inputs = triangle::Inputs {
+        shader: triangle::compose_shader(
+            "triangle_vertex_color.wgsl",
+            include_str!("../ch02/01_triangle_vertex_color/triangle_vertex_color.wgsl"),
+        ),
+        topology: wgpu::PrimitiveTopology::TriangleList,
+        strip_index_format: None,
+    };
+    triangle::State::with_init(init, &inputs, 3)
+}
+
+// ch02/02_triangle_primitive with the triangle-strip argument
+fn primitive_strip_state(init: ws::InitWgpu) -> triangle::State {
+    let inputs = triangle::Inputs {
+        shader: triangle::compose_shader(
+            "triangle_primitive.wgsl",
+            include_str!("../ch02/02_triangle_primitive/triangle_primitive.wgsl"),
+        ),
+        topology: wgpu::PrimitiveTopology::TriangleStrip,
+        strip_index_format: Some(wgpu::IndexFormat::Uint32),
+    };
+    triangle::State::with_init(init, &inputs, 9)
+}
+
+harness::golden_test!(triangle_vertex_color, 1, vertex_color_state);
+harness::golden_test!(triangle_primitive_strip, 1, primitive_strip_state);
+harness::golden_test!(line_msaa, 4, line_msaa::State::with_init);
@@ -0,0 +1,16 @@
+mod harness;
+
+#[allow(dead_code)]
+#[path = "../ch03/01_cube_rotation/state.rs"]
+mod state;
+#[allow(dead_code)]
+#[path = "../ch03/01_cube_rotation/vertex.rs"]
+mod vertex;
+
+harness::golden_test!(cube_rotation, 1, state::State::with_init, harness::TIME);
+harness::golden_test!(
+    cube_rotation_msaa,
+    4,
+    state::State::with_init,
+    harness::TIME
+);
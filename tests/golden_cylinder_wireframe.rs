@@ -0,0 +1,15 @@
+mod harness;
+
+#[allow(dead_code)]
+#[path = "../ch03/04_cylinder_wireframe/state.rs"]
+mod state;
+#[allow(dead_code)]
+#[path = "../ch03/04_cylinder_wireframe/vertex.rs"]
+mod vertex;
+
+harness::golden_test!(
+    cylinder_wireframe,
+    1,
+    state::State::with_init,
+    harness::TIME
+);
@@ -0,0 +1,10 @@
+mod harness;
+
+#[allow(dead_code)]
+#[path = "../ch03/06_many_cubes/state.rs"]
+mod state;
+#[allow(dead_code)]
+#[path = "../ch03/02_cube_wireframe/vertex.rs"]
+mod vertex;
+
+harness::golden_test!(many_cubes, 1, state::State::with_init, harness::TIME);
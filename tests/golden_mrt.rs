@@ -0,0 +1,69 @@
+// Draws one triangle into two color targets and checks both, with and without MSAA.
+mod harness;
+
+use wgpu_fundamentals::capture;
+use wgpu_fundamentals::wgpu_simplified as ws;
+
+const FORMATS: [wgpu::TextureFormat; 2] = [
+    wgpu::TextureFormat::Rgba8UnormSrgb,
+    wgpu::TextureFormat::Rgba16Float,
+];
+
+fn render_targets(name: &str, sample_count: u32) {
+    let Some(init) = harness::init(sample_count) else {
+        return;
+    };
+    let shader = init
+        .device
+        .create_shader_module(wgpu::include_wgsl!("mrt.wgsl"));
+    let color_targets = FORMATS.map(|format| Some(format.into()));
+    let mut ppl = ws::IRenderPipeline {
+        shader: Some(&shader),
+        is_depth_stencil: false,
+        color_targets: &color_targets,
+        ..Default::default()
+    };
+    let pipeline = ppl.new(&init);
+
+    let views = ws::create_color_target_views(&init, &FORMATS);
+    let msaa_views = ws::create_msaa_texture_views(&init, &FORMATS);
+    let view_refs: Vec<_> = views.iter().collect();
+    let msaa_view_refs: Vec<_> = msaa_views.iter().collect();
+    let color_attachments = if init.sample_count > 1 {
+        ws::create_msaa_color_attachments(&view_refs, &msaa_view_refs)
+    } else {
+        ws::create_color_attachments(&view_refs)
+    };
+
+    let mut encoder = init
+        .device
+        .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+    {
+        let mut render_pass = encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: Some("MRT Pass"),
+            color_attachments: &color_attachments,
+            depth_stencil_attachment: None,
+            occlusion_query_set: None,
+            timestamp_writes: None,
+            multiview_mask: None,
+        });
+        render_pass.set_pipeline(&pipeline);
+        render_pass.draw(0..3, 0..1);
+    }
+    init.queue.submit(std::iter::once(encoder.finish()));
+
+    for (view, target) in views.iter().zip(["color", "uv"]) {
+        let image = capture::capture_frame(&init, view.texture()).unwrap_or_else(|e| panic!("{e}"));
+        harness::assert_matches_golden(&format!("{name}_{target}"), &image);
+    }
+}
+
+#[test]
+fn mrt() {
+    render_targets("mrt", 1);
+}
+
+#[test]
+fn mrt_msaa() {
+    render_targets("mrt_msaa", 4);
+}
@@ -0,0 +1,15 @@
+mod harness;
+
+#[allow(dead_code)]
+#[path = "../ch03/07_picture_in_picture/state.rs"]
+mod state;
+#[allow(dead_code)]
+#[path = "../ch03/01_cube_rotation/vertex.rs"]
+mod vertex;
+
+harness::golden_test!(
+    picture_in_picture,
+    1,
+    state::State::with_init,
+    harness::TIME
+);
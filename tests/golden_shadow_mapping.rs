@@ -0,0 +1,29 @@
+mod harness;
+
+use wgpu_fundamentals::wgpu_simplified as ws;
+
+#[allow(dead_code)]
+#[path = "../ch03/08_shadow_mapping/state.rs"]
+mod state;
+#[allow(dead_code)]
+#[path = "../ch03/08_shadow_mapping/vertex.rs"]
+mod vertex;
+
+harness::golden_test!(shadow_mapping, 1, state::State::with_init, harness::TIME);
+
+// The shadow map keeps its own depth-only format when the scene's depth buffer has stencil.
+#[test]
+fn shadow_mapping_stencil() {
+    let options = ws::InitWgpuOptions {
+        depth_format: wgpu::TextureFormat::Depth24PlusStencil8,
+        ..Default::default()
+    };
+    let Some(init) = harness::init_with_options(1, options) else {
+        return;
+    };
+    let mut state = state::State::with_init(init);
+    state.update(harness::TIME);
+    state.render().unwrap();
+    // the same image as with the default depth format
+    harness::assert_matches_golden("shadow_mapping", &harness::capture(&state.init));
+}
@@ -0,0 +1,10 @@
+mod harness;
+
+#[allow(dead_code)]
+#[path = "../ch03/05_torus_wireframe/state.rs"]
+mod state;
+#[allow(dead_code)]
+#[path = "../ch03/05_torus_wireframe/vertex.rs"]
+mod vertex;
+
+harness::golden_test!(torus_wireframe, 1, state::State::with_init, harness::TIME);
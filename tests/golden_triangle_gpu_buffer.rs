@@ -0,0 +1,10 @@
+mod harness;
+
+#[allow(dead_code)]
+#[path = "../ch02/03_triangle_gpu_buffer/state.rs"]
+mod state;
+#[allow(dead_code)]
+#[path = "../ch02/03_triangle_gpu_buffer/vertex.rs"]
+mod vertex;
+
+harness::golden_test!(triangle_gpu_buffer, 1, state::State::with_init);
@@ -0,0 +1,209 @@
+// Golden-image helpers shared by the tests/golden_*.rs files. Each example scene is rendered
+// headlessly at a fixed size and time, captured, and compared with tests/golden/<name>.png.
+//
+// UPDATE_GOLDEN=1 cargo test   rewrites the reference images instead of comparing
+// REQUIRE_GPU=1 cargo test     fails instead of skipping when no adapter is available
+//
+// On a mismatch the captured image and a diff image (mismatched pixels in red over a dimmed
+// reference) are written to target/tmp/golden/.
+
+// Not every test binary uses every helper.
+#![allow(dead_code, unused_macros)]
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use wgpu_fundamentals::capture::{self, CapturedImage};
+use wgpu_fundamentals::wgpu_simplified as ws;
+
+pub const WIDTH: u32 = 256;
+pub const HEIGHT: u32 = 256;
+pub const FORMAT: wgpu::TextureFormat = wgpu::TextureFormat::Rgba8UnormSrgb;
+// Passed to `State::update`, so animated scenes render the same frame on every run.
+pub const TIME: Duration = Duration::from_millis(1500);
+
+#[derive(Clone, Copy, Debug)]
+pub struct Tolerance {
+    // Largest difference allowed in any channel of a pixel.
+    pub channel: u8,
+    // Fraction of pixels that may exceed `channel`, for rasterizers that differ on edges.
+    pub mismatched: f32,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Self {
+            channel: 2,
+            mismatched: 0.001,
+        }
+    }
+}
+
+// A headless InitWgpu on the fallback adapter, or on any CPU adapter such as llvmpipe, so
+// the results don't depend on the GPU. Returns None after printing why when there is
+// neither; the tests then pass without checking anything, unless REQUIRE_GPU is set.
+pub fn init(sample_count: u32) -> Option<ws::InitWgpu> {
+    init_with_options(sample_count, ws::InitWgpuOptions::default())
+}
+
+// `init` with other settings, such as the depth format. The adapter fields are replaced.
+pub fn init_with_options(sample_count: u32, options: ws::InitWgpuOptions) -> Option<ws::InitWgpu> {
+    let fallback = ws::InitWgpuOptions {
+        backends: wgpu::Backends::all(),
+        force_fallback_adapter: true,
+        clamp_sample_count: true,
+        ..options
+    };
+    let cpu = ws::InitWgpuOptions {
+        adapter: ws::AdapterSelector::DeviceType(wgpu::DeviceType::Cpu),
+        force_fallback_adapter: false,
+        ..fallback.clone()
+    };
+
+    let mut errors = vec![];
+    for options in [fallback, cpu] {
+        match pollster::block_on(ws::InitWgpu::try_init_headless_with_options(
+            WIDTH,
+            HEIGHT,
+            FORMAT,
+            sample_count,
+            &options,
+        )) {
+            // The count is clamped to one the adapter supports; a different count would render
+            // a different image, so that adapter can't run the test either.
+            Ok(init) if init.sample_count != sample_count => errors.push(format!(
+                "{} supports no {sample_count}x MSAA, only {}x",
+                init.adapter.get_info().name,
+                init.sample_count
+            )),
+            Ok(init) => return Some(init),
+            Err(e) => errors.push(e.to_string()),
+        }
+    }
+    let message = format!(
+        "no usable software or fallback adapter is available ({})",
+        errors.join("; ")
+    );
+    if std::env::var_os("REQUIRE_GPU").is_some() {
+        panic!("{message}");
+    }
+    // Written to stderr directly, so the test harness doesn't capture it.
+    let _ = writeln!(std::io::stderr(), "skipping golden-image test: {message}");
+    None
+}
+
+// A golden-image test of one example state:
+//
+// golden_test!(cube_rotation, 1, state::State::with_init, harness::TIME);
+//
+// builds the state from `init(1)`, passes the remaining arguments to `update`, renders, and
+// compares the frame with tests/golden/cube_rotation.png.
+macro_rules! golden_test {
+    ($name:ident, $sample_count:expr, $with_init:expr $(, $update_arg:expr)* $(,)?) => {
+        #[test]
+        fn $name() {
+            let Some(init) = $crate::harness::init($sample_count) else {
+                return;
+            };
+            let mut state = ($with_init)(init);
+            state.update($($update_arg),*);
+            state.render().unwrap();
+            $crate::harness::assert_matches_golden(
+                stringify!($name),
+                &$crate::harness::capture(&state.init),
+            );
+        }
+    };
+}
+#[allow(unused_imports)]
+pub(crate) use golden_test;
+
+pub fn capture(init: &ws::InitWgpu) -> CapturedImage {
+    let texture = init
+        .offscreen_texture
+        .as_ref()
+        .expect("golden-image tests render headless");
+    capture::capture_frame(init, texture).unwrap_or_else(|e| panic!("{e}"))
+}
+
+pub fn assert_matches_golden(name: &str, image: &CapturedImage) {
+    assert_matches_golden_with(name, image, Tolerance::default());
+}
+
+pub fn assert_matches_golden_with(name: &str, image: &CapturedImage, tolerance: Tolerance) {
+    let reference_path =
+        PathBuf::from(env!("CARGO_MANIFEST_DIR")).join(format!("tests/golden/{name}.png"));
+
+    if std::env::var_os("UPDATE_GOLDEN").is_some() {
+        image
+            .save_png(&reference_path)
+            .unwrap_or_else(|e| panic!("{e}"));
+        eprintln!("updated {}", reference_path.display());
+        return;
+    }
+
+    let reference = CapturedImage::load_png(&reference_path)
+        .unwrap_or_else(|e| panic!("{e}\nrun with UPDATE_GOLDEN=1 to create the reference image"));
+    assert_eq!(
+        (image.width, image.height),
+        (reference.width, reference.height),
+        "{name}: size differs from the reference image"
+    );
+
+    let (mismatched, diff) = compare(image, &reference, tolerance.channel);
+    let allowed = (tolerance.mismatched * (image.width * image.height) as f32) as usize;
+    if mismatched > allowed {
+        let output_dir = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden");
+        std::fs::create_dir_all(&output_dir).unwrap_or_else(|e| panic!("{e}"));
+        let actual_path = output_dir.join(format!("{name}.actual.png"));
+        let diff_path = output_dir.join(format!("{name}.diff.png"));
+        image
+            .save_png(&actual_path)
+            .unwrap_or_else(|e| panic!("{e}"));
+        diff.save_png(&diff_path).unwrap_or_else(|e| panic!("{e}"));
+        panic!(
+            "{name}: {mismatched} pixels differ from {} by more than {} (allowed: {allowed})\n\
+             actual: {}\ndiff: {}",
+            reference_path.display(),
+            tolerance.channel,
+            actual_path.display(),
+            diff_path.display(),
+        );
+    }
+}
+
+// Counts the pixels with a channel difference above `channel` and builds the diff image.
+fn compare(
+    image: &CapturedImage,
+    reference: &CapturedImage,
+    channel: u8,
+) -> (usize, CapturedImage) {
+    let mut mismatched = 0;
+    let mut rgba = Vec::with_capacity(image.rgba.len());
+    for (actual, expected) in image
+        .rgba
+        .chunks_exact(4)
+        .zip(reference.rgba.chunks_exact(4))
+    {
+        let differs = actual
+            .iter()
+            .zip(expected)
+            .any(|(a, e)| a.abs_diff(*e) > channel);
+        if differs {
+            mismatched += 1;
+            rgba.extend_from_slice(&[255, 0, 0, 255]);
+        } else {
+            let gray = (expected[0] as u32 + expected[1] as u32 + expected[2] as u32) / 12;
+            rgba.extend_from_slice(&[gray as u8, gray as u8, gray as u8, 255]);
+        }
+    }
+    (
+        mismatched,
+        CapturedImage {
+            width: image.width,
+            height: image.height,
+            rgba,
+        },
+    )
+}
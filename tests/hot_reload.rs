@@ -0,0 +1,69 @@
+mod harness;
+
+use std::fs::File;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+
+use wgpu_fundamentals::hot_reload::HotShader;
+use wgpu_fundamentals::shader_composer::ShaderComposer;
+
+const SHADER: &str = "#include \"color.wgsl\"
+
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return COLOR;
+}
+";
+
+// Writes the file and moves its modification time forward, so the change is seen even on
+// file systems with coarse timestamps.
+fn write(path: &Path, contents: &str, age: u64) {
+    std::fs::write(path, contents).unwrap();
+    let modified = SystemTime::now() - Duration::from_secs(age);
+    File::options()
+        .write(true)
+        .open(path)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+}
+
+#[test]
+fn include_changes_reload_the_shader() {
+    let Some(init) = harness::init(1) else {
+        return;
+    };
+    let dir = std::env::temp_dir().join(format!("hot_reload_{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let shader_path = dir.join("main.wgsl");
+    let include_path = dir.join("color.wgsl");
+    write(&shader_path, SHADER, 10);
+    write(&include_path, "const COLOR = vec4f(1.0);\n", 10);
+
+    let mut hot_shader = HotShader::load(
+        &init,
+        &shader_path,
+        &[("color.wgsl", include_path.as_path())],
+        ShaderComposer::new(),
+    )
+    .unwrap();
+    hot_shader.watcher.interval = Duration::ZERO;
+    for (_, watcher) in hot_shader.include_files.iter_mut() {
+        watcher.interval = Duration::ZERO;
+    }
+    assert!(!hot_shader.reload_if_changed(&init));
+
+    // a broken include keeps the old module
+    write(&include_path, "const COLOR = vec4f(1.0;\n", 5);
+    assert!(!hot_shader.reload_if_changed(&init));
+
+    write(
+        &include_path,
+        "const COLOR = vec4f(0.5, 0.5, 0.5, 1.0);\n",
+        0,
+    );
+    assert!(hot_shader.reload_if_changed(&init));
+    assert!(!hot_shader.reload_if_changed(&init));
+
+    std::fs::remove_dir_all(&dir).unwrap();
+}
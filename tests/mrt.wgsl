@@ -0,0 +1,27 @@
+// Writes a different image to each of two color targets.
+struct Output {
+    @builtin(position) Position: vec4f,
+    @location(0) vUv: vec2f,
+};
+
+struct Targets {
+    @location(0) color: vec4f,
+    @location(1) uv: vec4f,
+};
+
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> Output {
+    var pos = array<vec2f, 3>(vec2(0.0, 0.8), vec2(-0.8, -0.8), vec2(0.8, -0.8));
+    var output: Output;
+    output.Position = vec4(pos[index], 0.0, 1.0);
+    output.vUv = pos[index] * 0.5 + 0.5;
+    return output;
+}
+
+@fragment
+fn fs_main(@location(0) vUv: vec2f) -> Targets {
+    var targets: Targets;
+    targets.color = vec4(1.0, 0.5, 0.0, 1.0);
+    targets.uv = vec4(vUv, 0.0, 1.0);
+    return targets;
+}
@@ -0,0 +1,70 @@
+mod harness;
+
+use wgpu_fundamentals::wgpu_simplified as ws;
+
+const SHADER: &str = "
+@vertex
+fn vs_main(@builtin(vertex_index) index: u32) -> @builtin(position) vec4f {
+    return vec4f(f32(index), 0.0, 0.0, 1.0);
+}
+
+@fragment
+fn fs_main() -> @location(0) vec4f {
+    return vec4f(1.0);
+}
+";
+
+fn create_shader(init: &ws::InitWgpu) -> wgpu::ShaderModule {
+    init.device
+        .create_shader_module(wgpu::ShaderModuleDescriptor {
+            label: None,
+            source: wgpu::ShaderSource::Wgsl(SHADER.into()),
+        })
+}
+
+#[test]
+fn pipeline_cache_hit_and_miss() {
+    let Some(init) = harness::init(1) else {
+        return;
+    };
+    let shader = create_shader(&init);
+    let mut cache = ws::RenderPipelineCache::new();
+    let ppl = ws::IRenderPipeline {
+        shader: Some(&shader),
+        is_depth_stencil: false,
+        ..Default::default()
+    };
+
+    let pipeline = cache.get_or_create(&init, &ppl);
+    // only the label differs, which isn't part of the key
+    let relabeled = ws::IRenderPipeline {
+        label: "Another Label",
+        shader: Some(&shader),
+        is_depth_stencil: false,
+        ..Default::default()
+    };
+    assert_eq!(cache.get_or_create(&init, &relabeled), pipeline);
+    assert_eq!(cache.len(), 1);
+
+    let lines = ws::IRenderPipeline {
+        shader: Some(&shader),
+        is_depth_stencil: false,
+        topology: wgpu::PrimitiveTopology::LineList,
+        ..Default::default()
+    };
+    assert_ne!(cache.get_or_create(&init, &lines), pipeline);
+    assert_eq!(cache.len(), 2);
+
+    // modules are compared by identity, not by source
+    let same_source = create_shader(&init);
+    let other_module = ws::IRenderPipeline {
+        shader: Some(&same_source),
+        is_depth_stencil: false,
+        ..Default::default()
+    };
+    assert_ne!(cache.get_or_create(&init, &other_module), pipeline);
+    assert_eq!(cache.len(), 3);
+
+    assert_eq!(cache.get_or_create(&init, &ppl), pipeline);
+    assert_eq!(cache.len(), 3);
+}
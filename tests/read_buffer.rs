@@ -0,0 +1,59 @@
+mod harness;
+
+use wgpu::util::DeviceExt;
+use wgpu_fundamentals::wgpu_simplified as ws;
+
+fn create_buffer(init: &ws::InitWgpu, contents: &[u8]) -> wgpu::Buffer {
+    init.device
+        .create_buffer_init(&wgpu::util::BufferInitDescriptor {
+            label: None,
+            contents,
+            usage: wgpu::BufferUsages::COPY_SRC,
+        })
+}
+
+#[test]
+fn read_buffer_round_trip() {
+    let Some(init) = harness::init(1) else {
+        return;
+    };
+    let values: [u64; 3] = [1, u64::MAX, 42];
+    let buffer = create_buffer(&init, bytemuck::cast_slice(&values));
+    assert_eq!(ws::read_buffer::<u64>(&init, &buffer).unwrap(), values);
+}
+
+#[test]
+fn read_buffer_rejects_partial_elements() {
+    let Some(init) = harness::init(1) else {
+        return;
+    };
+    let buffer = create_buffer(&init, &[0; 12]);
+    assert!(matches!(
+        ws::read_buffer::<u64>(&init, &buffer),
+        Err(ws::ReadBufferError::Size {
+            size: 12,
+            element_size: 8
+        })
+    ));
+}
+
+#[test]
+fn read_buffer_rejects_unaligned_sizes() {
+    let Some(init) = harness::init(1) else {
+        return;
+    };
+    // three u16 elements, but copies need a multiple of 4 bytes
+    let buffer = init.device.create_buffer(&wgpu::BufferDescriptor {
+        label: None,
+        size: 6,
+        usage: wgpu::BufferUsages::COPY_SRC,
+        mapped_at_creation: false,
+    });
+    assert!(matches!(
+        ws::read_buffer::<u16>(&init, &buffer),
+        Err(ws::ReadBufferError::Size {
+            size: 6,
+            element_size: 2
+        })
+    ));
+}